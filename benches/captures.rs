@@ -0,0 +1,57 @@
+//! Times searching 100k lines one at a time, the way the program does, with patterns
+//! that have groups and the same patterns without them. A pattern without groups has no
+//! spans to record or to allocate storage for, so its `find` should take clearly less
+//! time than the one with groups. `captures` should cost little more than `find`.
+//! Each time is the best of a few runs, the others are disturbed by whatever else runs.
+//!
+//! Run with `cargo bench --bench captures`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use codecrafters_grep::Expression;
+
+const LINES: usize = 100_000;
+const RUNS: usize = 5;
+
+fn time_lines(lines: &[String], mut is_match: impl FnMut(&str) -> bool) -> (Duration, usize) {
+    let mut best = Duration::MAX;
+    let mut matches = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        matches = lines
+            .iter()
+            .filter(|line| black_box(is_match(black_box(line))))
+            .count();
+        best = best.min(start.elapsed());
+    }
+    (best, matches)
+}
+
+fn compile(pattern: &str) -> Expression {
+    Expression::try_from(pattern).expect("the pattern is valid")
+}
+
+fn main() {
+    let lines: Vec<String> = (0..LINES)
+        .map(|i| match i % 10 {
+            0 => format!("{i}: the worker ayz handled the requests"),
+            _ => format!("{i}: the worker handled some other requests"),
+        })
+        .collect();
+
+    for (grouped, plain) in [(r"(x|a)(y|b)z", r"[xa][yb]z"), (r"(w)(\w+)(s)", r"w\w+s")] {
+        let with = compile(grouped);
+        let without = compile(plain);
+        let (with_groups, found) = time_lines(&lines, |line| with.find(line).is_some());
+        let (without_groups, found_without) =
+            time_lines(&lines, |line| without.find(line).is_some());
+        let (captures, captured) = time_lines(&lines, |line| with.captures(line).is_some());
+        assert_eq!(found, found_without);
+        assert_eq!(found, captured);
+        println!(
+            "`{grouped}`: find {with_groups:?}, captures {captures:?}; \
+             `{plain}`: find {without_groups:?} over {LINES} lines"
+        );
+    }
+}
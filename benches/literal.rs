@@ -0,0 +1,40 @@
+//! Compares the substring search used for literal patterns with the matchers,
+//! which `[E]RROR` still goes through although it matches the same as `ERROR`.
+//!
+//! Run with `cargo bench`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use codecrafters_grep::Expression;
+
+const ROUNDS: u32 = 20;
+
+fn time_find_all(pattern: &str, input: &str) -> (Duration, usize) {
+    let expression = Expression::try_from(pattern).expect("the pattern is valid");
+    let start = Instant::now();
+    let mut matches = 0;
+    for _ in 0..ROUNDS {
+        matches = black_box(expression.find_all(black_box(input))).len();
+    }
+    (start.elapsed() / ROUNDS, matches)
+}
+
+fn main() {
+    let line = "2024-05-01 12:00:00 INFO request served in 12ms by worker 7\n";
+    let mut input = line.repeat(20_000);
+    input.push_str("2024-05-01 12:00:01 ERROR worker 7 stopped\n");
+    input.push_str(&line.repeat(20_000));
+
+    let (literal, literal_matches) = time_find_all("ERROR", &input);
+    let (general, general_matches) = time_find_all("[E]RROR", &input);
+    assert_eq!(literal_matches, general_matches);
+
+    println!("input of {} bytes", input.len());
+    println!("literal `ERROR`:   {literal:?} per search");
+    println!("matchers `[E]RROR`: {general:?} per search");
+    println!(
+        "speedup: {:.1}x",
+        general.as_secs_f64() / literal.as_secs_f64()
+    );
+}
@@ -0,0 +1,478 @@
+use std::cell::{Cell, RefCell};
+
+use crate::matcher::Matcher;
+
+/// What the search shares across its attempts at every position.
+pub(crate) struct MatchState {
+    /// The spans of the capture groups of the current match attempt, indexed by group.
+    pub(crate) groups: RefCell<Vec<Option<(usize, usize)>>>,
+    scratch: RefCell<Scratch>,
+    /// How many more times a matcher may be tried before the search gives up.
+    steps_left: Cell<usize>,
+    exceeded: Cell<bool>,
+}
+
+impl MatchState {
+    pub(crate) fn new(group_count: usize, max_steps: usize) -> Self {
+        Self {
+            groups: RefCell::new(vec![None; group_count]),
+            scratch: RefCell::default(),
+            steps_left: Cell::new(max_steps),
+            exceeded: Cell::new(false),
+        }
+    }
+
+    /// Counts trying a matcher, returns whether the budget allows it.
+    fn step(&self) -> bool {
+        match self.steps_left.get().checked_sub(1) {
+            Some(steps_left) => {
+                self.steps_left.set(steps_left);
+                true
+            }
+            None => {
+                self.exceeded.set(true);
+                false
+            }
+        }
+    }
+
+    /// Whether the search was cut short because it ran out of steps.
+    pub(crate) fn exceeded(&self) -> bool {
+        self.exceeded.get()
+    }
+}
+
+/// An instruction of a compiled expression. Each one either moves on to the next
+/// instruction or fails, which resumes the most recent choice left on the backtrack stack.
+#[derive(Debug, Clone)]
+enum Instruction {
+    /// Consumes a character accepted by a single character matcher.
+    Char(Matcher),
+    /// Checks a zero-width matcher like `^` or `\b` at the position.
+    Assert(Matcher),
+    Backreference(usize),
+    /// Goes on with the next instruction, or with `alternative` if that fails.
+    Split {
+        alternative: usize,
+    },
+    Jump(usize),
+    /// Remembers where the group starts.
+    GroupStart(usize),
+    /// Captures the group from where it started to the position.
+    GroupEnd(usize),
+    /// Consumes as many characters accepted by a single character matcher as possible
+    /// up to `max`, then gives them back one at a time down to `min`.
+    RepeatChar {
+        matcher: Matcher,
+        min: usize,
+        max: Option<usize>,
+    },
+    /// Starts counting the repetitions of a body following the next `Repeat`.
+    RepeatStart {
+        counter: usize,
+    },
+    /// Decides whether to match the body that follows once more or to go on with `exit`,
+    /// greedily preferring the body once `min` repetitions are done.
+    Repeat {
+        counter: usize,
+        min: usize,
+        max: Option<usize>,
+        exit: usize,
+    },
+    /// Counts a repetition of the body and goes back to its `Repeat`.
+    RepeatEnd {
+        counter: usize,
+        repeat: usize,
+        exit: usize,
+    },
+    Match,
+}
+
+/// An entry of the backtrack stack: a choice to resume or a change to undo on the way there.
+enum Backtrack {
+    Resume {
+        instruction: usize,
+        position: usize,
+    },
+    /// Gives back one more character of a `RepeatChar` that had matched `count` of them,
+    /// ending at `end`.
+    GiveBack {
+        instruction: usize,
+        end: usize,
+        count: usize,
+        min: usize,
+    },
+    RestoreGroup {
+        index: usize,
+        span: Option<(usize, usize)>,
+    },
+    RestoreStart {
+        index: usize,
+        start: usize,
+    },
+    RestoreCounter {
+        counter: usize,
+        value: Counter,
+    },
+}
+
+/// The storage of a match attempt, which the next attempt of the search reuses
+/// rather than allocating its own at every position.
+#[derive(Default)]
+struct Scratch {
+    /// Where each group started, until it ends and is captured.
+    starts: Vec<usize>,
+    counters: Vec<Counter>,
+    stack: Vec<Backtrack>,
+}
+
+/// The repetitions of a body done so far and where the last one ended.
+#[derive(Debug, Clone, Copy, Default)]
+struct Counter {
+    count: usize,
+    mark: usize,
+}
+
+/// The alternatives of an expression compiled to instructions for a backtracking search
+/// that keeps its choices on a stack of its own, so that long inputs don't take
+/// the call stack with them, e.g. `(ab)+` repeated thousands of times.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Program {
+    instructions: Vec<Instruction>,
+    counters: usize,
+}
+
+impl Program {
+    pub(crate) fn compile(alternatives: &[Vec<Matcher>]) -> Self {
+        let mut program = Self::default();
+        program.compile_alternatives(alternatives.iter().map(Vec::as_slice));
+        program.instructions.push(Instruction::Match);
+        program
+    }
+
+    /// Estimates the memory taken by the instructions in bytes.
+    pub(crate) fn approximate_size(&self) -> usize {
+        self.instructions
+            .iter()
+            .map(|instruction| match instruction {
+                Instruction::Char(matcher)
+                | Instruction::Assert(matcher)
+                | Instruction::RepeatChar { matcher, .. } => matcher.approximate_size(),
+                _ => std::mem::size_of::<Instruction>(),
+            })
+            .sum()
+    }
+
+    /// Compiles `first|second|...`, each alternative tried in turn.
+    fn compile_alternatives<'m>(&mut self, alternatives: impl Iterator<Item = &'m [Matcher]>) {
+        let mut alternatives = alternatives.peekable();
+        let mut jumps = Vec::new();
+        while let Some(matchers) = alternatives.next() {
+            let split = alternatives.peek().map(|_| {
+                self.instructions
+                    .push(Instruction::Split { alternative: 0 });
+                self.instructions.len() - 1
+            });
+            self.compile_sequence(matchers);
+            if let Some(split) = split {
+                self.instructions.push(Instruction::Jump(0));
+                jumps.push(self.instructions.len() - 1);
+                let alternative = self.instructions.len();
+                self.instructions[split] = Instruction::Split { alternative };
+            }
+        }
+        let end = self.instructions.len();
+        for jump in jumps {
+            self.instructions[jump] = Instruction::Jump(end);
+        }
+    }
+
+    fn compile_sequence(&mut self, matchers: &[Matcher]) {
+        for matcher in matchers {
+            self.compile_matcher(matcher);
+        }
+    }
+
+    fn compile_matcher(&mut self, matcher: &Matcher) {
+        match matcher {
+            Matcher::StartOfLine
+            | Matcher::EndOfLine
+            | Matcher::StartOfAnyLine
+            | Matcher::EndOfAnyLine
+            | Matcher::WordBoundary => self.instructions.push(Instruction::Assert(matcher.clone())),
+            Matcher::OneOrMore(matcher) => self.compile_repeat(matcher, 1, None),
+            Matcher::ZeroOrMore(matcher) => self.compile_repeat(matcher, 0, None),
+            Matcher::ZeroOrOne(matcher) => self.compile_repeat(matcher, 0, Some(1)),
+            Matcher::Repeat { matcher, min, max } => self.compile_repeat(matcher, *min, *max),
+            Matcher::Group { left, right, index } => {
+                if let Some(index) = index {
+                    self.instructions.push(Instruction::GroupStart(*index));
+                }
+                self.compile_alternatives(std::iter::once(left.as_slice()).chain(right.as_deref()));
+                if let Some(index) = index {
+                    self.instructions.push(Instruction::GroupEnd(*index));
+                }
+            }
+            Matcher::Backreference(n) => self.instructions.push(Instruction::Backreference(*n)),
+            // only the parser sees these, they match nothing of their own
+            Matcher::GroupStart
+            | Matcher::GroupEnd
+            | Matcher::Alteration
+            | Matcher::Quantifier(_)
+            | Matcher::NamedGroupStart(_)
+            | Matcher::SetFlags { .. }
+            | Matcher::FlagGroupStart { .. } => {}
+            matcher => self.instructions.push(Instruction::Char(matcher.clone())),
+        }
+    }
+
+    /// Compiles `matcher{min,max}`, which all the quantifiers come down to,
+    /// so that they backtrack alike.
+    fn compile_repeat(&mut self, matcher: &Matcher, min: usize, max: Option<usize>) {
+        if max == Some(0) {
+            return;
+        }
+        if matcher.is_single_char() {
+            // a single loop instead of a choice per character, more of them take more memory
+            self.instructions.push(Instruction::RepeatChar {
+                matcher: matcher.clone(),
+                min,
+                max,
+            });
+            return;
+        }
+        let counter = self.counters;
+        self.counters += 1;
+        self.instructions.push(Instruction::RepeatStart { counter });
+        let repeat = self.instructions.len();
+        self.instructions.push(Instruction::Repeat {
+            counter,
+            min,
+            max,
+            exit: 0,
+        });
+        self.compile_matcher(matcher);
+        let exit = self.instructions.len() + 1;
+        self.instructions.push(Instruction::RepeatEnd {
+            counter,
+            repeat,
+            exit,
+        });
+        self.instructions[repeat] = Instruction::Repeat {
+            counter,
+            min,
+            max,
+            exit,
+        };
+    }
+
+    /// Matches the program at `position` of `input` and calls `accept` with the end of each
+    /// match and the groups it captured, in order of preference, until it accepts one.
+    /// Returns whether one was accepted, leaving the groups it captured in `state`.
+    /// Positions are byte offsets and always fall on character boundaries.
+    pub(crate) fn run(
+        &self,
+        input: &str,
+        position: usize,
+        state: &MatchState,
+        mut accept: impl FnMut(usize, &[Option<(usize, usize)>]) -> bool,
+    ) -> bool {
+        let mut groups = state.groups.borrow_mut();
+        groups.fill(None);
+        let mut scratch = state.scratch.borrow_mut();
+        let Scratch {
+            starts,
+            counters,
+            stack,
+        } = &mut *scratch;
+        starts.clear();
+        starts.resize(groups.len(), 0);
+        counters.clear();
+        counters.resize(self.counters, Counter::default());
+        // a successful attempt leaves its choices behind
+        stack.clear();
+        let mut instruction = 0;
+        let mut position = position;
+        loop {
+            // once out of steps every attempt fails, which ends the whole search quickly
+            if !state.step() {
+                return false;
+            }
+            let matched =
+                match &self.instructions[instruction] {
+                    Instruction::Char(matcher) => match input[position..].chars().next() {
+                        Some(c) if matcher.match_char(c) => {
+                            position += c.len_utf8();
+                            true
+                        }
+                        _ => false,
+                    },
+                    Instruction::Assert(matcher) => matcher.is_match_at(input, position),
+                    Instruction::Backreference(n) => {
+                        // a group that did not participate in the match can't be referred to
+                        let span = n.checked_sub(1).and_then(|i| *groups.get(i)?);
+                        match span.and_then(|(start, end)| input.get(start..end)) {
+                            Some(captured) if input[position..].starts_with(captured) => {
+                                position += captured.len();
+                                true
+                            }
+                            _ => false,
+                        }
+                    }
+                    Instruction::Split { alternative } => {
+                        stack.push(Backtrack::Resume {
+                            instruction: *alternative,
+                            position,
+                        });
+                        true
+                    }
+                    Instruction::Jump(target) => {
+                        instruction = *target;
+                        continue;
+                    }
+                    Instruction::GroupStart(index) => {
+                        let start = std::mem::replace(&mut starts[*index], position);
+                        stack.push(Backtrack::RestoreStart {
+                            index: *index,
+                            start,
+                        });
+                        true
+                    }
+                    Instruction::GroupEnd(index) => {
+                        // the last repetition of a quantified group overwrites the capture of the
+                        // previous ones, which is brought back if an earlier repetition is retried;
+                        // likewise a failed alternative doesn't leave its capture behind, so that
+                        // `(abc|a)(def)\1` compares against what the alternative that matched captured
+                        let span = groups[*index].replace((starts[*index], position));
+                        stack.push(Backtrack::RestoreGroup {
+                            index: *index,
+                            span,
+                        });
+                        true
+                    }
+                    Instruction::RepeatChar { matcher, min, max } => {
+                        let mut end = position;
+                        let mut count = 0;
+                        while let Some(c) = input[end..].chars().next().filter(|c| {
+                            max.map_or(true, |max| count < max) && matcher.match_char(*c)
+                        }) {
+                            end += c.len_utf8();
+                            count += 1;
+                        }
+                        if count > *min {
+                            stack.push(Backtrack::GiveBack {
+                                instruction: instruction + 1,
+                                end,
+                                count,
+                                min: *min,
+                            });
+                        }
+                        position = end;
+                        count >= *min
+                    }
+                    Instruction::RepeatStart { counter } => {
+                        let value = std::mem::replace(
+                            &mut counters[*counter],
+                            Counter {
+                                count: 0,
+                                mark: position,
+                            },
+                        );
+                        stack.push(Backtrack::RestoreCounter {
+                            counter: *counter,
+                            value,
+                        });
+                        true
+                    }
+                    Instruction::Repeat {
+                        counter,
+                        min,
+                        max,
+                        exit,
+                    } => {
+                        let count = counters[*counter].count;
+                        if max == &Some(count) {
+                            instruction = *exit;
+                            continue;
+                        }
+                        if count >= *min {
+                            stack.push(Backtrack::Resume {
+                                instruction: *exit,
+                                position,
+                            });
+                        }
+                        true
+                    }
+                    Instruction::RepeatEnd {
+                        counter,
+                        repeat,
+                        exit,
+                    } => {
+                        let value = counters[*counter];
+                        if position == value.mark {
+                            // a zero-width repetition would repeat forever, e.g. `(a?)+`, and
+                            // the repetitions still missing would match the same empty string
+                            instruction = *exit;
+                            continue;
+                        }
+                        counters[*counter] = Counter {
+                            count: value.count + 1,
+                            mark: position,
+                        };
+                        stack.push(Backtrack::RestoreCounter {
+                            counter: *counter,
+                            value,
+                        });
+                        instruction = *repeat;
+                        continue;
+                    }
+                    Instruction::Match => {
+                        if accept(position, &groups) {
+                            return true;
+                        }
+                        false
+                    }
+                };
+            if matched {
+                instruction += 1;
+                continue;
+            }
+            // resume the latest choice, undoing what was changed since it was made
+            loop {
+                match stack.pop() {
+                    None => return false,
+                    Some(Backtrack::Resume {
+                        instruction: resumed,
+                        position: resumed_position,
+                    }) => {
+                        instruction = resumed;
+                        position = resumed_position;
+                        break;
+                    }
+                    Some(Backtrack::GiveBack {
+                        instruction: resumed,
+                        end,
+                        count,
+                        min,
+                    }) => {
+                        let end = end - input[..end].chars().next_back().map_or(1, char::len_utf8);
+                        if count - 1 > min {
+                            stack.push(Backtrack::GiveBack {
+                                instruction: resumed,
+                                end,
+                                count: count - 1,
+                                min,
+                            });
+                        }
+                        instruction = resumed;
+                        position = end;
+                        break;
+                    }
+                    Some(Backtrack::RestoreGroup { index, span }) => groups[index] = span,
+                    Some(Backtrack::RestoreStart { index, start }) => starts[index] = start,
+                    Some(Backtrack::RestoreCounter { counter, value }) => counters[counter] = value,
+                }
+            }
+        }
+    }
+}
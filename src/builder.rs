@@ -0,0 +1,56 @@
+use crate::Expression;
+
+/// Compiles patterns into an [`Expression`] with matching flags, which are all off by default
+/// just like for [`Expression::try_from`].
+#[derive(Debug, Clone, Default)]
+pub struct RegexBuilder {
+    ignore_case: bool,
+    dot_matches_newline: bool,
+    multiline: bool,
+    max_steps: Option<usize>,
+}
+
+impl RegexBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lets letters match both their lowercase and uppercase forms.
+    pub fn ignore_case(&mut self, enabled: bool) -> &mut Self {
+        self.ignore_case = enabled;
+        self
+    }
+
+    /// Lets `.` match newlines too.
+    pub fn dot_matches_newline(&mut self, enabled: bool) -> &mut Self {
+        self.dot_matches_newline = enabled;
+        self
+    }
+
+    /// Lets `^` and `$` match at the start and the end of every line of the input.
+    pub fn multiline(&mut self, enabled: bool) -> &mut Self {
+        self.multiline = enabled;
+        self
+    }
+
+    /// Limits how many times a matcher may be tried during one search, so that a pattern that
+    /// backtracks catastrophically like `(a+)+b` on many `a`s gives up instead of hanging.
+    /// A search out of steps finds no match, [`Expression::try_find`] reports it as an error.
+    /// Unlimited by default.
+    pub fn max_steps(&mut self, max_steps: usize) -> &mut Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    /// Compiles `pattern` with the flags set so far.
+    pub fn build(&self, pattern: &str) -> Result<Expression, String> {
+        let expression = Expression::try_from(pattern)?
+            .dot_matches_newline(self.dot_matches_newline)
+            .multiline(self.multiline)
+            .max_steps(self.max_steps.unwrap_or(usize::MAX));
+        Ok(match self.ignore_case {
+            true => expression.ignore_case(),
+            false => expression,
+        })
+    }
+}
@@ -0,0 +1,71 @@
+use std::borrow::Cow;
+
+/// Bytes that may not be valid UTF-8, decoded into text that an expression can search.
+/// The valid UTF-8 sequences stay the characters they are and each invalid byte becomes
+/// the character with the same code, like in Latin-1, so that `café` still matches
+/// next to an invalid byte and ASCII patterns, `\d`, `\w` or `[...]` match around them.
+/// Offsets into the text convert back to offsets into the bytes.
+#[derive(Debug, Clone)]
+pub struct DecodedBytes {
+    text: String,
+    /// The offsets into `text` of the characters decoded from invalid bytes, in order.
+    invalid: Vec<usize>,
+}
+
+impl DecodedBytes {
+    pub fn new(bytes: &[u8]) -> Self {
+        let mut text = String::with_capacity(bytes.len());
+        let mut invalid = Vec::new();
+        for chunk in bytes.utf8_chunks() {
+            text.push_str(chunk.valid());
+            for &byte in chunk.invalid() {
+                invalid.push(text.len());
+                text.push(char::from(byte));
+            }
+        }
+        Self { text, invalid }
+    }
+
+    /// The decoded text.
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    /// Converts the offset `position` into the text into the offset into the bytes.
+    pub fn byte_offset(&self, position: usize) -> usize {
+        // an invalid byte is decoded into a character of two bytes
+        position - self.invalid.partition_point(|&offset| offset < position)
+    }
+
+    /// The number of decoded bytes.
+    pub fn len(&self) -> usize {
+        self.byte_offset(self.text.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// The bytes decoded into the text from the offset `start` to `end`.
+    pub fn bytes(&self, start: usize, end: usize) -> Cow<'_, [u8]> {
+        let first = self.invalid.partition_point(|&offset| offset < start);
+        let last = self.invalid.partition_point(|&offset| offset < end);
+        let invalid = &self.invalid[first..last];
+        if invalid.is_empty() {
+            return Cow::Borrowed(&self.text.as_bytes()[start..end]);
+        }
+        let mut bytes = Vec::with_capacity(end - start);
+        let mut copied = start;
+        for &offset in invalid {
+            bytes.extend_from_slice(&self.text.as_bytes()[copied..offset]);
+            let c = self.text[offset..]
+                .chars()
+                .next()
+                .expect("a byte was decoded here");
+            bytes.push(c as u8);
+            copied = offset + c.len_utf8();
+        }
+        bytes.extend_from_slice(&self.text.as_bytes()[copied..end]);
+        Cow::Owned(bytes)
+    }
+}
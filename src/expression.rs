@@ -0,0 +1,736 @@
+use std::fmt;
+use std::mem;
+use std::sync::Arc;
+
+use crate::backtrack::{MatchState, Program};
+use crate::bytes::DecodedBytes;
+use crate::matcher::Matcher;
+
+/// The spans of a match and of the groups captured by it.
+/// Group 0 is the whole match, the capture groups are numbered from 1.
+///
+/// Like all spans reported by [`Expression`], these are byte offsets into the input,
+/// so `&input[start..end]` is always valid, even after multibyte characters.
+pub struct Captures<'t> {
+    input: &'t str,
+    spans: Vec<Option<(usize, usize)>>,
+    /// The names of the groups from group 1 on, shared with the expression.
+    names: Arc<[Option<String>]>,
+}
+
+impl<'t> Captures<'t> {
+    /// Returns the `(start, end)` span of group `index`, if it participated in the match.
+    pub fn get(&self, index: usize) -> Option<(usize, usize)> {
+        self.spans.get(index).copied().flatten()
+    }
+
+    /// Returns the text captured by group `index`, if it participated in the match.
+    pub fn text(&self, index: usize) -> Option<&'t str> {
+        self.get(index).map(|(start, end)| &self.input[start..end])
+    }
+
+    /// Returns the text captured by the group called `name`, if it participated in the match.
+    pub fn name(&self, name: &str) -> Option<&'t str> {
+        let index = self
+            .names
+            .iter()
+            .position(|group| group.as_deref() == Some(name))?;
+        self.text(index + 1)
+    }
+
+    /// Appends `template` to `output`, substituting `$n` with the text of group `n`
+    /// (empty if the group did not participate) and `$$` with a literal `$`.
+    fn expand(&self, template: &str, output: &mut String) {
+        let mut remainder = template;
+        while let Some(dollar) = remainder.find('$') {
+            output.push_str(&remainder[..dollar]);
+            remainder = &remainder[dollar + 1..];
+            let digits = remainder.bytes().take_while(u8::is_ascii_digit).count();
+            if let Some(escaped) = remainder.strip_prefix('$') {
+                output.push('$');
+                remainder = escaped;
+            } else if digits == 0 {
+                output.push('$');
+            } else {
+                if let Ok(index) = remainder[..digits].parse() {
+                    output.push_str(self.text(index).unwrap_or_default());
+                }
+                remainder = &remainder[digits..];
+            }
+        }
+        output.push_str(remainder);
+    }
+}
+
+/// An iterator over the non-overlapping matches of an expression in an input
+/// together with their captures, created by [`Expression::captures_iter`].
+pub struct CapturesIter<'e, 't> {
+    expression: &'e Expression,
+    input: &'t str,
+    /// Where to search for the next match, `None` once the input is exhausted.
+    position: Option<usize>,
+}
+
+impl<'t> Iterator for CapturesIter<'_, 't> {
+    type Item = Captures<'t>;
+
+    fn next(&mut self) -> Option<Captures<'t>> {
+        let position = self.position?;
+        let captures =
+            self.expression
+                .captures_from(self.input, position, MatchKind::LeftmostFirst);
+        self.position = captures
+            .as_ref()
+            .and_then(|captures| captures.get(0))
+            .and_then(|span| position_after(self.input, span));
+        captures
+    }
+}
+
+/// An iterator over the spans of the non-overlapping matches of an expression in an input,
+/// created by [`Expression::matches_iter`]. Each match is only searched for when asked for.
+pub struct MatchesIter<'e, 't> {
+    expression: &'e Expression,
+    input: &'t str,
+    /// Where to search for the next match, `None` once the input is exhausted.
+    position: Option<usize>,
+}
+
+impl Iterator for MatchesIter<'_, '_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        let position = self.position?;
+        let span = self.expression.find_at(self.input, position);
+        self.position = span.and_then(|span| position_after(self.input, span));
+        span
+    }
+}
+
+/// Where to search for the next of the non-overlapping matches in `input` after the match
+/// spanning from `start` to `end`. That is the end of the match, or the character after it
+/// for an empty match so that the search makes progress. `None` once `input` is exhausted.
+fn position_after(input: &str, (start, end): (usize, usize)) -> Option<usize> {
+    match end > start {
+        true => Some(end),
+        false => input[end..].chars().next().map(|c| end + c.len_utf8()),
+    }
+}
+
+/// How to choose between the matches that start at the leftmost position.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MatchKind {
+    /// The first match in order of preference wins, like in Perl: `a|ab` finds `a` in `ab`.
+    #[default]
+    LeftmostFirst,
+    /// The longest match wins, like in POSIX: `a|ab` finds `ab` in `ab`.
+    LeftmostLongest,
+}
+
+/// A compiled regular expression.
+///
+/// Spans of matches are `(start, end)` byte offsets into the input, not character counts.
+pub struct Expression {
+    /// The top-level alternatives: `cat|dog` matches if either of them does.
+    alternatives: Vec<Vec<Matcher>>,
+    /// The names of the capture groups in the order of their numbers, `None` if unnamed.
+    group_names: Arc<[Option<String>]>,
+    /// How many times a matcher may be tried during one search, see `RegexBuilder::max_steps`.
+    max_steps: usize,
+    /// The alternatives compiled for the search, see `compile`.
+    program: Program,
+    /// The text of an expression made of nothing but literal characters, which is searched
+    /// for as a substring instead of trying the matchers at each position.
+    literal: Option<String>,
+}
+
+impl Expression {
+    /// Makes `.` match newlines too when `enabled`, which it doesn't by default.
+    pub(crate) fn dot_matches_newline(mut self, enabled: bool) -> Self {
+        self.visit_matchers(|matcher| match matcher {
+            Matcher::Wildcard if enabled => *matcher = Matcher::AnyChar,
+            Matcher::AnyChar if !enabled => *matcher = Matcher::Wildcard,
+            _ => {}
+        });
+        self
+    }
+
+    /// Makes `^` and `$` match at the start and the end of every line of the input
+    /// when `enabled`, instead of only at the start and the end of the whole input.
+    pub(crate) fn multiline(mut self, enabled: bool) -> Self {
+        self.visit_matchers(|matcher| match matcher {
+            Matcher::StartOfLine if enabled => *matcher = Matcher::StartOfAnyLine,
+            Matcher::EndOfLine if enabled => *matcher = Matcher::EndOfAnyLine,
+            Matcher::StartOfAnyLine if !enabled => *matcher = Matcher::StartOfLine,
+            Matcher::EndOfAnyLine if !enabled => *matcher = Matcher::EndOfLine,
+            _ => {}
+        });
+        self
+    }
+
+    /// Limits how many times a matcher may be tried during one search.
+    pub(crate) fn max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Makes every character matcher case-insensitive. Back references still compare
+    /// the captured text exactly.
+    pub(crate) fn ignore_case(mut self) -> Self {
+        self.visit_matchers(Matcher::ignore_case);
+        self
+    }
+
+    /// Estimates the memory taken by the compiled expression in bytes, e.g. to keep a cache
+    /// of expressions within a budget. It includes the storage for the captures that
+    /// every search allocates.
+    pub fn approximate_size(&self) -> usize {
+        let alternatives: usize = self
+            .alternatives
+            .iter()
+            .map(|matchers| {
+                mem::size_of::<Vec<Matcher>>()
+                    + matchers
+                        .iter()
+                        .map(Matcher::approximate_size)
+                        .sum::<usize>()
+            })
+            .sum();
+        let names: usize = self
+            .group_names
+            .iter()
+            .map(|name| mem::size_of::<Option<String>>() + name.as_ref().map_or(0, String::len))
+            .sum();
+        let captures = self.group_names.len() * mem::size_of::<Option<(usize, usize)>>();
+        mem::size_of::<Self>() + alternatives + self.program.approximate_size() + names + captures
+    }
+
+    /// Checks that `pattern` is well-formed, reporting the same errors as `try_from`,
+    /// for callers that only need to know whether it would compile.
+    pub fn validate(pattern: &str) -> Result<(), String> {
+        Self::try_from(pattern).map(|_| ())
+    }
+
+    /// Finds the leftmost match in `input` and returns its `(start, end)` span.
+    pub fn find(&self, input: &str) -> Option<(usize, usize)> {
+        self.find_with(input, MatchKind::LeftmostFirst)
+    }
+
+    /// Like [`find`](Self::find), choosing between the matches at the leftmost position by `kind`.
+    pub fn find_with(&self, input: &str, kind: MatchKind) -> Option<(usize, usize)> {
+        self.find_from(input, 0, kind)
+    }
+
+    /// Finds the leftmost match in `input`, which doesn't have to be valid UTF-8, and returns
+    /// its span in bytes. The input is searched as decoded by [`DecodedBytes`].
+    pub fn find_bytes(&self, input: &[u8]) -> Option<(usize, usize)> {
+        let text = DecodedBytes::new(input);
+        let (start, end) = self.find(text.as_str())?;
+        Some((text.byte_offset(start), text.byte_offset(end)))
+    }
+
+    /// Finds every non-overlapping match in `input` from left to right and returns their spans.
+    /// After an empty match the search goes on from the next character, so that `a*`
+    /// finds `(0, 0)`, `(1, 3)` and `(3, 3)` in `baa`.
+    pub fn find_all(&self, input: &str) -> Vec<(usize, usize)> {
+        let mut spans = Vec::new();
+        self.for_each_match(input, |captures| {
+            spans.push(captures.get(0).expect("group 0 is the whole match"));
+        });
+        spans
+    }
+
+    /// Finds the leftmost match in `input` together with the groups it captured.
+    /// A pattern without groups still reports the whole match as group 0.
+    pub fn captures<'t>(&self, input: &'t str) -> Option<Captures<'t>> {
+        self.captures_with(input, MatchKind::LeftmostFirst)
+    }
+
+    /// Like [`captures`](Self::captures), choosing between the matches at the leftmost position by `kind`.
+    pub fn captures_with<'t>(&self, input: &'t str, kind: MatchKind) -> Option<Captures<'t>> {
+        self.captures_from(input, 0, kind)
+    }
+
+    /// Replaces the leftmost match in `input` with `replacement`, where `$1`, `$2`, ...
+    /// refer to the captured groups and `$0` to the whole match.
+    pub fn replace(&self, input: &str, replacement: &str) -> String {
+        let Some(captures) = self.captures(input) else {
+            return input.to_owned();
+        };
+        let (start, end) = captures.get(0).expect("group 0 is the whole match");
+        let mut result = String::with_capacity(input.len());
+        result.push_str(&input[..start]);
+        captures.expand(replacement, &mut result);
+        result.push_str(&input[end..]);
+        result
+    }
+
+    /// Replaces every non-overlapping match in `input` with `replacement`, expanding
+    /// the group references in it like [`replace`](Self::replace) does.
+    /// An empty `replacement` deletes the matches.
+    pub fn replace_all(&self, input: &str, replacement: &str) -> String {
+        self.replace_with(input, |captures, output| {
+            captures.expand(replacement, output)
+        })
+    }
+
+    /// Deletes every non-overlapping match in `input`.
+    pub fn remove_all(&self, input: &str) -> String {
+        self.replace_all(input, "")
+    }
+
+    /// Splits `input` by the matches.
+    pub fn split<'t>(&self, input: &'t str) -> Vec<&'t str> {
+        let mut pieces = Vec::new();
+        let mut copied = 0;
+        self.for_each_match(input, |captures| {
+            let (start, end) = captures.get(0).expect("group 0 is the whole match");
+            pieces.push(&input[copied..start]);
+            copied = end;
+        });
+        pieces.push(&input[copied..]);
+        pieces
+    }
+
+    /// Splits `input` by the matches like [`split`](Self::split) does, but puts the text
+    /// captured by the groups of each match between the pieces, like JavaScript's
+    /// `String.split` with a capturing regular expression. Groups that did not participate
+    /// in a match are put as an empty string.
+    pub fn split_captures<'t>(&self, input: &'t str) -> Vec<&'t str> {
+        let mut pieces = Vec::new();
+        let mut copied = 0;
+        self.for_each_match(input, |captures| {
+            let (start, end) = captures.get(0).expect("group 0 is the whole match");
+            pieces.push(&input[copied..start]);
+            pieces.extend((1..captures.spans.len()).map(|i| captures.text(i).unwrap_or_default()));
+            copied = end;
+        });
+        pieces.push(&input[copied..]);
+        pieces
+    }
+
+    fn replace_with(&self, input: &str, mut replace: impl FnMut(&Captures, &mut String)) -> String {
+        let mut result = String::with_capacity(input.len());
+        let mut copied = 0;
+        self.for_each_match(input, |captures| {
+            let (start, end) = captures.get(0).expect("group 0 is the whole match");
+            result.push_str(&input[copied..start]);
+            replace(&captures, &mut result);
+            copied = end;
+        });
+        result.push_str(&input[copied..]);
+        result
+    }
+
+    /// Iterates over every non-overlapping match in `input` from left to right together with
+    /// the groups it captured, e.g. to pick the named fields out of each entry of a log.
+    /// Each match only reports what it captured itself, nothing is left over from the previous one.
+    pub fn captures_iter<'e, 't>(&'e self, input: &'t str) -> CapturesIter<'e, 't> {
+        CapturesIter {
+            expression: self,
+            input,
+            position: Some(0),
+        }
+    }
+
+    /// Iterates over the spans of the matches [`find_all`](Self::find_all) finds,
+    /// searching for each one only when it is asked for, so that a caller who stops early
+    /// doesn't scan the whole input.
+    pub fn matches_iter<'e, 't>(&'e self, input: &'t str) -> MatchesIter<'e, 't> {
+        MatchesIter {
+            expression: self,
+            input,
+            position: Some(0),
+        }
+    }
+
+    /// Calls `f` for every non-overlapping match in `input` from left to right.
+    fn for_each_match<'t>(&self, input: &'t str, f: impl FnMut(Captures<'t>)) {
+        self.captures_iter(input).for_each(f);
+    }
+
+    /// Finds the leftmost match in `input` that starts at `position` or later.
+    /// Unlike searching in `&input[position..]`, anchors still refer to the whole `input`.
+    /// There is no match if `position` is past the end or not on a character boundary.
+    pub fn find_at(&self, input: &str, position: usize) -> Option<(usize, usize)> {
+        self.find_from(input, position, MatchKind::LeftmostFirst)
+    }
+
+    /// Like [`find_at`](Self::find_at), choosing between the matches at the leftmost position
+    /// by `kind`.
+    pub fn find_at_with(
+        &self,
+        input: &str,
+        position: usize,
+        kind: MatchKind,
+    ) -> Option<(usize, usize)> {
+        self.find_from(input, position, kind)
+    }
+
+    /// Like [`find_at_with`](Self::find_at_with), but only finds a match whose span `accept`
+    /// accepts, e.g. one that is a whole word. All the matches at a position are tried,
+    /// in the order `kind` prefers them, before the search moves on to the next position,
+    /// so `a.|a` finds `a` in `a b` when the match must be followed by a space.
+    pub fn find_at_where(
+        &self,
+        input: &str,
+        position: usize,
+        kind: MatchKind,
+        accept: impl FnMut(usize, usize) -> bool,
+    ) -> Option<(usize, usize)> {
+        self.find_in(input, position, kind, &self.new_state(), accept)
+    }
+
+    /// Checks whether the expression matches a prefix of `text`, i.e. a match starting at 0.
+    pub fn is_match_at_start(&self, text: &str) -> bool {
+        let state = self.new_state();
+        self.match_at(text, 0, MatchKind::LeftmostFirst, &state, |_| true)
+            .is_some()
+            && !state.exceeded()
+    }
+
+    /// Checks whether the expression matches a suffix of `text`, i.e. a match ending
+    /// exactly at `text.len()`, as if the pattern was followed by `$`.
+    pub fn is_match_at_end(&self, text: &str) -> bool {
+        let state = self.new_state();
+        self.search(text, 0, &state, |start| {
+            self.matches_between(text, start, text.len(), &state)
+                .then_some(())
+        })
+        .is_some()
+    }
+
+    /// Checks whether the expression matches the whole of `input`, as if it was wrapped
+    /// in `^` and `$`, e.g. to validate that a string only consists of digits with `\d+`.
+    pub fn is_full_match(&self, input: &str) -> bool {
+        let state = self.new_state();
+        self.matches_between(input, 0, input.len(), &state) && !state.exceeded()
+    }
+
+    /// Like [`find`](Self::find), but reports a search that ran out of the steps allowed by
+    /// [`RegexBuilder::max_steps`](crate::RegexBuilder::max_steps) as an error instead of
+    /// as no match.
+    pub fn try_find(&self, input: &str) -> Result<Option<(usize, usize)>, String> {
+        let state = self.new_state();
+        let found = self.find_in(input, 0, MatchKind::LeftmostFirst, &state, |_, _| true);
+        if state.exceeded() {
+            Err(format!("Exceeded the limit of {} steps", self.max_steps))
+        } else {
+            Ok(found)
+        }
+    }
+
+    /// Checks whether some match spans exactly from `start` to `end` of `input`,
+    /// backtracking into the shorter and longer candidates until one ends there.
+    fn matches_between(&self, input: &str, start: usize, end: usize, state: &MatchState) -> bool {
+        self.program.run(input, start, state, |e, _| e == end)
+    }
+
+    fn find_from(&self, input: &str, position: usize, kind: MatchKind) -> Option<(usize, usize)> {
+        self.find_in(input, position, kind, &self.new_state(), |_, _| true)
+    }
+
+    fn find_in(
+        &self,
+        input: &str,
+        position: usize,
+        kind: MatchKind,
+        state: &MatchState,
+        mut accept: impl FnMut(usize, usize) -> bool,
+    ) -> Option<(usize, usize)> {
+        // the substring search takes no steps, so it is only used when they are not counted
+        if let Some(literal) = self
+            .literal
+            .as_ref()
+            .filter(|_| self.max_steps == usize::MAX)
+        {
+            let mut position = position;
+            loop {
+                let start = position + input.get(position..)?.find(literal.as_str())?;
+                let end = start + literal.len();
+                if accept(start, end) {
+                    return Some((start, end));
+                }
+                position = start + input[start..].chars().next()?.len_utf8();
+            }
+        }
+        self.search(input, position, state, |start| {
+            let end = self.match_at(input, start, kind, state, |end| accept(start, end))?;
+            Some((start, end))
+        })
+    }
+
+    fn captures_from<'t>(
+        &self,
+        input: &'t str,
+        position: usize,
+        kind: MatchKind,
+    ) -> Option<Captures<'t>> {
+        let state = self.new_state();
+        let (start, end) = self.find_in(input, position, kind, &state, |_, _| true)?;
+        let spans = std::iter::once(Some((start, end)))
+            .chain(state.groups.into_inner())
+            .collect();
+        Some(Captures {
+            input,
+            spans,
+            names: Arc::clone(&self.group_names),
+        })
+    }
+
+    /// The state shared by all the attempts of a search, with the step budget of the search.
+    /// Without groups the storage for their spans stays empty and never allocates, only
+    /// the backtrack stack is allocated, once per search, and shared by its attempts.
+    fn new_state(&self) -> MatchState {
+        MatchState::new(self.group_names.len(), self.max_steps)
+    }
+
+    /// Calls `attempt` for each position from `position` on until it succeeds.
+    /// A search that runs out of steps finds nothing, not even a match it had found
+    /// before when looking for the longest one.
+    fn search<T>(
+        &self,
+        input: &str,
+        mut position: usize,
+        state: &MatchState,
+        mut attempt: impl FnMut(usize) -> Option<T>,
+    ) -> Option<T> {
+        // a position past the end or inside a character can't start a match
+        if !input.is_char_boundary(position) {
+            return None;
+        }
+        // `<=` so that an empty remainder is tried too: the empty pattern and `^$` match ""
+        while position <= input.len() {
+            let found = attempt(position);
+            if state.exceeded() {
+                return None;
+            } else if found.is_some() {
+                return found;
+            } else if self.anchored_at_start() {
+                return None;
+            }
+            position += input[position..].chars().next().map_or(1, char::len_utf8);
+        }
+        None
+    }
+
+    /// Matches the expression at `position` of `input` and returns the end of the match,
+    /// leaving the groups it captured in `state`. Only the ends that `accept` accepts count.
+    fn match_at(
+        &self,
+        input: &str,
+        position: usize,
+        kind: MatchKind,
+        state: &MatchState,
+        mut accept: impl FnMut(usize) -> bool,
+    ) -> Option<usize> {
+        let mut end = None;
+        let mut longest_groups = Vec::new();
+        let found = self.program.run(input, position, state, |e, groups| {
+            if !accept(e) {
+                return false;
+            }
+            match kind {
+                MatchKind::LeftmostFirst => {
+                    end = Some(e);
+                    true
+                }
+                // reject every match to explore all of them, remembering the longest one
+                MatchKind::LeftmostLongest => {
+                    if end.map_or(true, |end| e > end) {
+                        end = Some(e);
+                        longest_groups = groups.to_vec();
+                    }
+                    // nothing can be longer than the rest of the input
+                    e == input.len()
+                }
+            }
+        });
+        if kind == MatchKind::LeftmostLongest && !found && end.is_some() {
+            *state.groups.borrow_mut() = longest_groups;
+        }
+        end
+    }
+
+    /// Calls `f` with every matcher of the expression, including the nested ones.
+    fn visit_matchers(&mut self, mut f: impl FnMut(&mut Matcher)) {
+        for matcher in self.alternatives.iter_mut().flatten() {
+            matcher.visit_mut(&mut f);
+        }
+        self.compile();
+    }
+
+    /// Compiles the matchers for the search again after they were built or changed,
+    /// e.g. by `ignore_case`, which also turns the literals into something else.
+    fn compile(&mut self) {
+        self.program = Program::compile(&self.alternatives);
+        self.literal = match self.alternatives.as_slice() {
+            [matchers] => matchers
+                .iter()
+                .map(|matcher| match matcher {
+                    Matcher::Literal(c) => Some(*c),
+                    _ => None,
+                })
+                .collect(),
+            _ => None,
+        };
+    }
+
+    /// An expression whose alternatives all start with `^` can only match at the start of the input.
+    fn anchored_at_start(&self) -> bool {
+        self.alternatives
+            .iter()
+            .all(|matchers| matches!(matchers.first(), Some(Matcher::StartOfLine)))
+    }
+}
+
+/// Writes the expression back as a pattern that compiles to an equivalent expression,
+/// e.g. to check what the parser made of a pattern. It need not be the original pattern:
+/// flags are written out as `(?i:...)` around each character they apply to.
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, matchers) in self.alternatives.iter().enumerate() {
+            if index > 0 {
+                write!(f, "|")?;
+            }
+            Matcher::write_sequence(f, matchers, &self.group_names)?;
+        }
+        Ok(())
+    }
+}
+
+struct Group {
+    start_index: usize,
+    alternative_index: Option<usize>,
+    /// Capturing groups are numbered by their opening parenthesis, also when nested.
+    index: Option<usize>,
+    /// Whether the enclosing group was case-insensitive, restored at the end of this one.
+    outer_ignore_case: bool,
+}
+
+impl TryFrom<&str> for Expression {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let mut pattern_index = 0;
+        let mut alternatives = Vec::new();
+        let mut matchers = Vec::new();
+        let mut groups = Vec::new();
+        let mut group_names = Vec::new();
+        // set by `(?i)` for the rest of the enclosing group and by `(?i:` inside its group
+        let mut ignore_case = false;
+        while pattern_index < value.len() {
+            let remainder = &value[pattern_index..];
+            match Matcher::try_parse(remainder)? {
+                (start @ (Matcher::GroupStart | Matcher::NamedGroupStart(_)), offset) => {
+                    let name = match start {
+                        Matcher::NamedGroupStart(name) => Some(name),
+                        _ => None,
+                    };
+                    if let Some(name) = name.as_ref() {
+                        if group_names.iter().flatten().any(|other| other == name) {
+                            return Err(format!("Duplicate group name '{name}'"));
+                        }
+                    }
+                    groups.push(Group {
+                        start_index: matchers.len(),
+                        alternative_index: None,
+                        index: Some(group_names.len()),
+                        outer_ignore_case: ignore_case,
+                    });
+                    group_names.push(name);
+                    pattern_index += offset;
+                }
+                (Matcher::FlagGroupStart { ignore_case: set }, offset) => {
+                    groups.push(Group {
+                        start_index: matchers.len(),
+                        alternative_index: None,
+                        index: None,
+                        outer_ignore_case: ignore_case,
+                    });
+                    ignore_case |= set;
+                    pattern_index += offset;
+                }
+                (Matcher::SetFlags { ignore_case: set }, offset) => {
+                    ignore_case |= set;
+                    pattern_index += offset;
+                }
+                (Matcher::Alteration, offset) => {
+                    if let Some(group) = groups.last_mut() {
+                        if group.alternative_index.is_some() {
+                            return Err("Double alteration in group".into());
+                        }
+                        group.alternative_index = Some(matchers.len());
+                    } else {
+                        // outside of groups, `|` separates the alternatives of the whole expression
+                        alternatives.push(mem::take(&mut matchers));
+                    }
+                    pattern_index += offset;
+                }
+                (Matcher::GroupEnd, offset) => {
+                    let group = groups.pop().ok_or("Stray )".to_owned())?;
+                    let right = group
+                        .alternative_index
+                        .map(|alternative_index| matchers.split_off(alternative_index));
+                    let left = matchers.split_off(group.start_index);
+                    matchers.push(Matcher::Group {
+                        left,
+                        right,
+                        index: group.index,
+                    });
+                    ignore_case = group.outer_ignore_case;
+                    pattern_index += offset;
+                }
+                (Matcher::Quantifier(quantifier), offset) => {
+                    // the matchers before the current group or alternative are not repeatable
+                    let first_repeatable = groups.last().map_or(0, |group| {
+                        group.alternative_index.unwrap_or(group.start_index)
+                    });
+                    let previous = match matchers.pop() {
+                        Some(previous)
+                            if matchers.len() >= first_repeatable && previous.is_quantifiable() =>
+                        {
+                            previous
+                        }
+                        _ => return Err("Nothing to repeat".into()),
+                    };
+                    matchers.push(quantifier.apply(previous));
+                    pattern_index += offset;
+                }
+                (matcher @ Matcher::Backreference(n), offset) => {
+                    if n == 0 || group_names.len() < n {
+                        return Err("Invalid back reference".into());
+                    }
+                    matchers.push(matcher);
+                    pattern_index += offset;
+                }
+                (mut matcher, offset) => {
+                    if ignore_case {
+                        matcher.ignore_case();
+                    }
+                    matchers.push(matcher);
+                    pattern_index += offset;
+                }
+            }
+        }
+        if !groups.is_empty() {
+            Err("Unclosed group".into())
+        } else {
+            alternatives.push(matchers);
+            let mut expression = Self {
+                alternatives,
+                group_names: group_names.into(),
+                max_steps: usize::MAX,
+                program: Program::default(),
+                literal: None,
+            };
+            expression.compile();
+            Ok(expression)
+        }
+    }
+}
+
+/// Checks whether `expression` matches anywhere in `input_line`.
+pub fn match_pattern(input_line: &str, expression: &Expression) -> bool {
+    expression.find(input_line).is_some()
+}
@@ -0,0 +1,89 @@
+/// A pattern taken literally and searched as a plain substring, like `grep -F`.
+pub struct FixedString {
+    needle: String,
+    ignore_case: bool,
+}
+
+impl FixedString {
+    pub fn new(needle: &str, ignore_case: bool) -> Self {
+        Self {
+            needle: needle.to_owned(),
+            ignore_case,
+        }
+    }
+
+    /// Finds the leftmost occurrence of the needle in `haystack` and returns its `(start, end)` span.
+    pub fn find(&self, haystack: &str) -> Option<(usize, usize)> {
+        if !self.ignore_case {
+            return haystack
+                .find(&self.needle)
+                .map(|start| (start, start + self.needle.len()));
+        }
+        // case folding may change the byte length of a character,
+        // so the end of an occurrence is only known after comparing it
+        haystack
+            .char_indices()
+            .map(|(start, _)| start)
+            .chain(std::iter::once(haystack.len()))
+            .find_map(|start| {
+                self.case_insensitive_prefix(&haystack[start..])
+                    .map(|length| (start, start + length))
+            })
+    }
+
+    /// Checks whether the whole of `haystack` is the needle.
+    pub fn is_full_match(&self, haystack: &str) -> bool {
+        if self.ignore_case {
+            self.case_insensitive_prefix(haystack) == Some(haystack.len())
+        } else {
+            haystack == self.needle
+        }
+    }
+
+    /// Returns the length of the prefix of `haystack` equal to the needle ignoring case.
+    fn case_insensitive_prefix(&self, haystack: &str) -> Option<usize> {
+        let mut haystack_chars = haystack.char_indices();
+        for needle_char in self.needle.chars() {
+            let (_, c) = haystack_chars.next()?;
+            if !c.to_lowercase().eq(needle_char.to_lowercase()) {
+                return None;
+            }
+        }
+        Some(
+            haystack_chars
+                .next()
+                .map_or(haystack.len(), |(index, _)| index),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FixedString;
+
+    #[test]
+    fn find() {
+        let needle = FixedString::new("a.c", false);
+        assert_eq!(needle.find("xxa.cy"), Some((2, 5)));
+        assert_eq!(needle.find("abc"), None);
+        assert_eq!(FixedString::new("", false).find("abc"), Some((0, 0)));
+    }
+
+    #[test]
+    fn find_ignoring_case() {
+        let needle = FixedString::new("ABC", true);
+        assert_eq!(needle.find("xabcy"), Some((1, 4)));
+        assert_eq!(needle.find("xAbCy"), Some((1, 4)));
+        assert_eq!(FixedString::new("ABC", false).find("xabcy"), None);
+        // the span covers the characters of the haystack, which may be longer
+        assert_eq!(FixedString::new("k", true).find("a\u{212a}"), Some((1, 4)));
+    }
+
+    #[test]
+    fn full_match() {
+        assert!(FixedString::new("abc", false).is_full_match("abc"));
+        assert!(!FixedString::new("abc", false).is_full_match("abcd"));
+        assert!(FixedString::new("abc", true).is_full_match("ABC"));
+        assert!(!FixedString::new("abc", true).is_full_match("ABCD"));
+    }
+}
@@ -0,0 +1,11 @@
+mod backtrack;
+mod builder;
+mod bytes;
+mod expression;
+mod fixed;
+mod matcher;
+
+pub use builder::RegexBuilder;
+pub use bytes::DecodedBytes;
+pub use expression::{match_pattern, Captures, CapturesIter, Expression, MatchKind, MatchesIter};
+pub use fixed::FixedString;
@@ -1,325 +1,1010 @@
-use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::VecDeque;
 use std::env;
-use std::io;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::iter;
+use std::mem;
+use std::path::{Path, PathBuf};
 use std::process;
 
-#[derive(Debug, Clone)]
-enum Matcher {
-    StartOfLine,
-    EndOfLine,
-    WordChar,
-    Digit,
-    // TODO: &str
-    PositiveCharGroup(String),
-    NegativeCharGroup(String),
-    Literal(char),
-    OneOrMore(Box<Matcher>),
-    ZeroOrOne(Box<Matcher>),
-    Wildcard,
-    GroupStart,
-    GroupEnd,
-    Alteration,
-    Group(Vec<Matcher>, Vec<Matcher>),
-    Backreference(usize),
+use codecrafters_grep::{DecodedBytes, Expression, FixedString, MatchKind, RegexBuilder};
+
+/// The pattern given on the command line, compiled according to the mode flags.
+enum Search {
+    Fixed(FixedString),
+    Expression(Expression),
 }
 
-impl Matcher {
-    fn match_some<'a>(
+impl Search {
+    /// Finds the leftmost match in `line` starting at `start` or later that `accept`
+    /// accepts, picking among the matches there according to `kind`.
+    fn find_at(
         &self,
-        string: &'a str,
-        matched_groups: &RefCell<Vec<&'a str>>,
-    ) -> Option<usize> {
-        let c = string.chars().next()?;
+        line: &str,
+        start: usize,
+        kind: MatchKind,
+        mut accept: impl FnMut(usize, usize) -> bool,
+    ) -> Option<(usize, usize)> {
         match self {
-            Self::StartOfLine
-            | Self::EndOfLine
-            | Self::GroupStart
-            | Self::GroupEnd
-            | Self::Alteration => Some(0),
-            Self::WordChar => {
-                (matches!(c, 'a'..='z') || matches!(c, 'A'..='Z') || c == '_').then_some(1)
+            Self::Fixed(fixed) => {
+                let mut position = start;
+                loop {
+                    let (begin, end) = fixed.find(&line[position..])?;
+                    let (begin, end) = (position + begin, position + end);
+                    if accept(begin, end) {
+                        return Some((begin, end));
+                    }
+                    position = begin + line[begin..].chars().next()?.len_utf8();
+                }
             }
-            Self::Digit => matches!(c, '0'..='9').then_some(1),
-            Self::PositiveCharGroup(g) => g.contains(c).then_some(1),
-            Self::NegativeCharGroup(g) => (!g.contains(c)).then_some(1),
-            Self::Literal(l) => (*l == c).then_some(1),
-            Self::OneOrMore(matcher) => Self::match_sequence(matcher, string, matched_groups),
-            Self::ZeroOrOne(matcher) => matcher.match_some(string, matched_groups).or(Some(0)),
-            Self::Wildcard => Some(1),
-            Self::Group(left, right) => Self::match_group(left, string, matched_groups)
-                .or_else(|| Self::match_group(right, string, matched_groups)),
-            Self::Backreference(n) => string
-                .starts_with(matched_groups.borrow()[*n - 1])
-                .then(|| matched_groups.borrow()[*n - 1].len()),
-        }
-    }
-
-    fn parse_backreference(pattern: &str) -> Option<(usize, usize)> {
-        if !pattern.starts_with("\\") {
-            return None;
-        }
-        let number_size = pattern
-            .chars()
-            .skip(1)
-            .take_while(|c| c.is_numeric())
-            .count();
-        if number_size == 0 {
-            return None;
-        }
-        let number = pattern[1..=number_size].parse().ok()?;
-        Some((number, number_size + 1))
-    }
-
-    fn try_parse(pattern: &str, previous: Option<&Matcher>) -> Option<(Self, usize)> {
-        if pattern.starts_with("^") {
-            Some((Self::StartOfLine, 1))
-        } else if pattern.starts_with("$") {
-            Some((Self::EndOfLine, 1))
-        } else if pattern.starts_with("\\d") {
-            Some((Self::Digit, 2))
-        } else if pattern.starts_with("\\w") {
-            Some((Self::WordChar, 2))
-        } else if let Some((number, length)) = Self::parse_backreference(pattern) {
-            Some((Self::Backreference(number), length))
-        } else if pattern.starts_with("[^") {
-            pattern
-                .find(']')
-                .map(|end| (Self::NegativeCharGroup(pattern[2..end].to_owned()), end + 1))
-        } else if pattern.starts_with("[") {
-            pattern
-                .find(']')
-                .map(|end| (Self::PositiveCharGroup(pattern[1..end].to_owned()), end + 1))
-        } else if pattern.starts_with("+") {
-            Some((Self::OneOrMore(Box::new(previous?.clone())), 1))
-        } else if pattern.starts_with("?") {
-            Some((Self::ZeroOrOne(Box::new(previous?.clone())), 1))
-        } else if pattern.starts_with(".") {
-            Some((Self::Wildcard, 1))
-        } else if pattern.starts_with("(") {
-            Some((Self::GroupStart, 1))
-        } else if pattern.starts_with(")") {
-            Some((Self::GroupEnd, 1))
-        } else if pattern.starts_with("|") {
-            Some((Self::Alteration, 1))
-        } else {
-            Some((Self::Literal(pattern.chars().next()?), 1))
+            Self::Expression(expression) => expression.find_at_where(line, start, kind, accept),
         }
     }
 
-    fn match_sequence<'a>(
-        matcher: &Matcher,
-        string: &'a str,
-        matched_groups: &RefCell<Vec<&'a str>>,
-    ) -> Option<usize> {
-        let mut match_count = 0;
-        loop {
-            let remainder = &string[match_count..];
-            if let Some(matched) = matcher.match_some(remainder, matched_groups) {
-                match_count += matched;
-            } else {
+    fn is_full_match(&self, line: &str) -> bool {
+        match self {
+            Self::Fixed(fixed) => fixed.is_full_match(line),
+            Self::Expression(expression) => expression.is_full_match(line),
+        }
+    }
+}
+
+/// The compiled patterns together with the flags restricting where they may match.
+/// A line matches if any of the patterns does.
+struct Pattern {
+    searches: Vec<Search>,
+    /// Only matches that are whole words count, `-w`.
+    whole_words: bool,
+    /// Only matches of the whole line count, `-x`.
+    whole_lines: bool,
+}
+
+impl Pattern {
+    /// Finds the first match in `line`, which is enough to select the line and to tell
+    /// the column of its first match. Its end may fall short of the longest match there.
+    fn find_first(&self, line: &str) -> Option<(usize, usize)> {
+        self.find_at(line, 0, MatchKind::LeftmostFirst, false)
+    }
+
+    /// Finds the leftmost match of any of the patterns in `line` starting at `start` or later,
+    /// the longest one if several patterns match there. Empty matches are passed over
+    /// when `non_empty`.
+    fn find_at(
+        &self,
+        line: &str,
+        start: usize,
+        kind: MatchKind,
+        non_empty: bool,
+    ) -> Option<(usize, usize)> {
+        self.searches
+            .iter()
+            .filter_map(|search| self.find_one_at(search, line, start, kind, non_empty))
+            .min_by_key(|&(begin, end)| (begin, Reverse(end)))
+    }
+
+    /// Finds the leftmost match of `search` in `line` starting at `start` or later.
+    /// With `-w`, matches next to a word character are passed over, and a shorter or longer
+    /// match at the same position may be the whole word: `-w 'a.|a'` finds `a` in `a b`.
+    /// With `-x`, the only possible match is the whole line.
+    fn find_one_at(
+        &self,
+        search: &Search,
+        line: &str,
+        start: usize,
+        kind: MatchKind,
+        non_empty: bool,
+    ) -> Option<(usize, usize)> {
+        if self.whole_lines {
+            let found = start == 0 && !(non_empty && line.is_empty()) && search.is_full_match(line);
+            return found.then_some((0, line.len()));
+        }
+        search.find_at(line, start, kind, |begin, end| {
+            (!non_empty || end > begin) && (!self.whole_words || is_whole_word(line, begin, end))
+        })
+    }
+
+    /// Finds all the non-overlapping matches in `line`, leaving out empty ones like `grep -o` does.
+    /// Like grep, it prefers the longest match, `a|ab` finds all of `ab`.
+    fn matches(&self, line: &str) -> Vec<(usize, usize)> {
+        let mut matches = Vec::new();
+        let mut position = 0;
+        while let Some((start, end)) =
+            self.find_at(line, position, MatchKind::LeftmostLongest, true)
+        {
+            matches.push((start, end));
+            position = end;
+        }
+        matches
+    }
+}
+
+/// Whether the span from `start` to `end` of `line` is neither preceded nor followed
+/// by a word character: a letter, a digit or `_`.
+fn is_whole_word(line: &str, start: usize, end: usize) -> bool {
+    let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+    !line[..start].chars().next_back().is_some_and(is_word_char)
+        && !line[end..].chars().next().is_some_and(is_word_char)
+}
+
+/// The order of the files found by `-r`, set by `--sort=SORT`.
+#[derive(Default, Clone, Copy)]
+enum Sort {
+    /// The order of the file system.
+    #[default]
+    None,
+    Path,
+}
+
+/// When to highlight the matches, set by `--color[=WHEN]`.
+#[derive(Default, Clone, Copy)]
+enum Color {
+    #[default]
+    Never,
+    Always,
+    /// Only when printing to a terminal.
+    Auto,
+}
+
+const HIGHLIGHT_START: &str = "\x1b[31m";
+const HIGHLIGHT_END: &str = "\x1b[0m";
+
+struct Options {
+    fixed_strings: bool,
+    ignore_case: bool,
+    whole_words: bool,
+    whole_lines: bool,
+    before_context: usize,
+    /// The number of lines printed after each selected line, `-A`.
+    after_context: usize,
+    only_matching: bool,
+    line_number: bool,
+    column: bool,
+    invert_match: bool,
+    count: bool,
+    /// Prints the number of matches instead of matching lines, `--count-matches`.
+    count_matches: bool,
+    /// Prints only the names of the files with a selected line, `-l`.
+    files_with_matches: bool,
+    max_count: Option<usize>,
+    /// Lines longer than this many bytes are skipped with a warning.
+    max_line_length: Option<usize>,
+    no_messages: bool,
+    /// Keeps the `\r` of `\r\n` line endings as part of the line, `-U`.
+    binary: bool,
+    /// Searches the files inside directories too, `-r`.
+    recursive: bool,
+    /// Follows symbolic links inside directories with `-r`, `-S`/`--follow`.
+    follow: bool,
+    /// Directories not descended into with `-r`, as globs of their names.
+    exclude_dirs: Vec<String>,
+    /// If given, the only directories descended into with `-r`, as globs of their names.
+    include_dirs: Vec<String>,
+    sort: Sort,
+    color: Color,
+    /// Separates the file name, the line number and the column from the content of a matching line.
+    field_separator: String,
+    /// The same as `field_separator` for context lines.
+    field_context_separator: String,
+    /// Printed between groups of lines with context that are not next to each other.
+    context_separator: String,
+    pattern: Option<String>,
+    /// The patterns given by `-e`, instead of taking the first argument.
+    patterns: Vec<String>,
+    /// Files to read the patterns from, `-f`, instead of taking the first argument.
+    pattern_files: Vec<String>,
+    /// The files to search, standard input if empty or for `-`.
+    files: Vec<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            fixed_strings: false,
+            ignore_case: false,
+            whole_words: false,
+            whole_lines: false,
+            before_context: 0,
+            after_context: 0,
+            only_matching: false,
+            line_number: false,
+            column: false,
+            invert_match: false,
+            count: false,
+            count_matches: false,
+            files_with_matches: false,
+            max_count: None,
+            max_line_length: None,
+            no_messages: false,
+            binary: false,
+            recursive: false,
+            follow: false,
+            exclude_dirs: Vec::new(),
+            include_dirs: Vec::new(),
+            sort: Sort::default(),
+            color: Color::default(),
+            field_separator: ":".into(),
+            field_context_separator: "-".into(),
+            context_separator: "--".into(),
+            pattern: None,
+            patterns: Vec::new(),
+            pattern_files: Vec::new(),
+            files: Vec::new(),
+        }
+    }
+}
+
+impl Options {
+    /// Parses the options, which may come before or after the pattern and the files
+    /// unless separated from them by `--`.
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self::default();
+        let mut positional = Vec::new();
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                positional.extend(args.by_ref());
                 break;
             }
+            if let Some(long) = arg.strip_prefix("--") {
+                options.parse_long(long)?;
+                continue;
+            }
+            match arg.strip_prefix('-') {
+                // short flags may be combined, e.g. `-Fi`
+                Some(flags) if !flags.is_empty() => {
+                    for (index, flag) in flags.char_indices() {
+                        match flag {
+                            'E' => options.fixed_strings = false,
+                            'F' => options.fixed_strings = true,
+                            'i' => options.ignore_case = true,
+                            'w' => options.whole_words = true,
+                            'x' => options.whole_lines = true,
+                            'o' => options.only_matching = true,
+                            'n' => options.line_number = true,
+                            'v' => options.invert_match = true,
+                            'c' => options.count = true,
+                            'l' => options.files_with_matches = true,
+                            's' => options.no_messages = true,
+                            'r' => options.recursive = true,
+                            'S' => options.follow = true,
+                            'U' => options.binary = true,
+                            'B' => {
+                                let value = flag_value(flag, &flags[index + 1..], &mut args)?;
+                                options.before_context = parse_number(flag, &value)?;
+                                break;
+                            }
+                            'A' => {
+                                let value = flag_value(flag, &flags[index + 1..], &mut args)?;
+                                options.after_context = parse_number(flag, &value)?;
+                                break;
+                            }
+                            'C' => {
+                                let value = flag_value(flag, &flags[index + 1..], &mut args)?;
+                                options.before_context = parse_number(flag, &value)?;
+                                options.after_context = options.before_context;
+                                break;
+                            }
+                            'e' => {
+                                let value = flag_value(flag, &flags[index + 1..], &mut args)?;
+                                options.patterns.push(value);
+                                break;
+                            }
+                            'f' => {
+                                let value = flag_value(flag, &flags[index + 1..], &mut args)?;
+                                options.pattern_files.push(value);
+                                break;
+                            }
+                            'm' => {
+                                let value = flag_value(flag, &flags[index + 1..], &mut args)?;
+                                options.max_count = Some(parse_number(flag, &value)?);
+                                break;
+                            }
+                            _ => return Err(format!("Unknown option '-{flag}'")),
+                        }
+                    }
+                }
+                // `-` alone is standard input
+                _ => positional.push(arg),
+            }
+        }
+        let mut positional = positional.into_iter();
+        // with `-e` or `-f` all the arguments are files
+        if options.patterns.is_empty() && options.pattern_files.is_empty() {
+            options.pattern = positional.next();
         }
+        options.files = positional.collect();
+        Ok(options)
+    }
 
-        if match_count > 0 {
-            Some(match_count)
+    /// Parses a long option without its leading `--`, its value follows a `=`.
+    fn parse_long(&mut self, option: &str) -> Result<(), String> {
+        let (name, value) = match option.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (option, None),
+        };
+        match (name, value) {
+            ("column", None) => self.column = true,
+            ("color" | "colour", None | Some("auto")) => self.color = Color::Auto,
+            ("color" | "colour", Some("always")) => self.color = Color::Always,
+            ("color" | "colour", Some("never")) => self.color = Color::Never,
+            ("max-count", Some(value)) => {
+                self.max_count = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid number '{value}' for --max-count"))?,
+                )
+            }
+            ("max-line-length", Some(value)) => {
+                self.max_line_length = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid number '{value}' for --max-line-length"))?,
+                )
+            }
+            ("count-matches", None) => self.count_matches = true,
+            ("files-with-matches", None) => self.files_with_matches = true,
+            ("no-messages", None) => self.no_messages = true,
+            ("recursive", None) => self.recursive = true,
+            ("follow", None) => self.follow = true,
+            ("binary", None) => self.binary = true,
+            ("exclude-dir", Some(glob)) => self.exclude_dirs.push(glob.into()),
+            ("include-dir", Some(glob)) => self.include_dirs.push(glob.into()),
+            ("sort", Some("none")) => self.sort = Sort::None,
+            ("sort", Some("path")) => self.sort = Sort::Path,
+            ("field-separator", Some(separator)) => self.field_separator = separator.into(),
+            ("field-context-separator", Some(separator)) => {
+                self.field_context_separator = separator.into();
+            }
+            ("context-separator", Some(separator)) => self.context_separator = separator.into(),
+            _ => return Err(format!("Unknown option '--{option}'")),
+        }
+        Ok(())
+    }
+
+    fn highlight(&self) -> bool {
+        match self.color {
+            Color::Never => false,
+            Color::Always => true,
+            Color::Auto => io::stdout().is_terminal(),
+        }
+    }
+
+    fn compile(&self) -> Result<Pattern, String> {
+        let patterns = self.patterns()?;
+        let searches = patterns
+            .iter()
+            .map(|pattern| self.compile_one(pattern))
+            .collect::<Result<_, _>>()?;
+        Ok(Pattern {
+            searches,
+            whole_words: self.whole_words,
+            whole_lines: self.whole_lines,
+        })
+    }
+
+    /// The patterns given by `-e` and by `-f`, one per non-blank line of the files,
+    /// or otherwise the one given as the first argument.
+    fn patterns(&self) -> Result<Vec<String>, String> {
+        if self.patterns.is_empty() && self.pattern_files.is_empty() {
+            let pattern = self.pattern.clone().ok_or("Expected a pattern")?;
+            return Ok(vec![pattern]);
+        }
+        let mut patterns = self.patterns.clone();
+        for path in &self.pattern_files {
+            let content = fs::read_to_string(path).map_err(|error| format!("{path}: {error}"))?;
+            let lines = content.lines().filter(|line| !line.trim().is_empty());
+            patterns.extend(lines.map(str::to_owned));
+        }
+        Ok(patterns)
+    }
+
+    fn compile_one(&self, pattern: &str) -> Result<Search, String> {
+        if self.fixed_strings {
+            Ok(Search::Fixed(FixedString::new(pattern, self.ignore_case)))
         } else {
-            None
+            RegexBuilder::new()
+                .ignore_case(self.ignore_case)
+                .build(pattern)
+                .map(Search::Expression)
         }
     }
+}
+
+/// Takes the value of a short flag, which either follows the flag directly (`-B2`)
+/// or is the next argument (`-B 2`).
+fn flag_value(
+    flag: char,
+    attached: &str,
+    args: &mut impl Iterator<Item = String>,
+) -> Result<String, String> {
+    if attached.is_empty() {
+        args.next()
+            .ok_or_else(|| format!("Expected a value after -{flag}"))
+    } else {
+        Ok(attached.to_owned())
+    }
+}
+
+/// Directories skipped by `-r` unless `--include-dir` is given.
+const DEFAULT_EXCLUDE_DIRS: [&str; 2] = [".git", "target"];
+
+/// Whether `-r` descends into the directory called `name`: it must not match `--exclude-dir`,
+/// and it must match `--include-dir` if given, otherwise not be one of `DEFAULT_EXCLUDE_DIRS`.
+fn descends_into(options: &Options, name: &str) -> bool {
+    let matches = |globs: &[String]| globs.iter().any(|glob| glob_matches(glob, name));
+    if matches(&options.exclude_dirs) {
+        false
+    } else if options.include_dirs.is_empty() {
+        !DEFAULT_EXCLUDE_DIRS.contains(&name)
+    } else {
+        matches(&options.include_dirs)
+    }
+}
 
-    fn match_group<'a>(
-        matchers: &[Matcher],
-        string: &'a str,
-        matched_groups: &RefCell<Vec<&'a str>>,
-    ) -> Option<usize> {
-        if matchers.is_empty() {
-            return None;
+/// Matches `name` against a shell glob, where `*` stands for any text and `?` for any character.
+fn glob_matches(glob: &str, name: &str) -> bool {
+    let mut glob_chars = glob.chars();
+    match glob_chars.next() {
+        None => name.is_empty(),
+        Some('*') => {
+            let rest = glob_chars.as_str();
+            name.char_indices()
+                .map(|(index, _)| index)
+                .chain(iter::once(name.len()))
+                .any(|index| glob_matches(rest, &name[index..]))
         }
-        // start group
-        let group_index = matched_groups.borrow().len();
-        matched_groups.borrow_mut().push("");
-        let mut match_len = 0;
-        for m in matchers {
-            let remainder = &string[match_len..];
-            match_len += m.match_some(remainder, matched_groups)?;
+        Some(g) => {
+            let mut name_chars = name.chars();
+            match name_chars.next() {
+                Some(c) if g == '?' || g == c => {
+                    glob_matches(glob_chars.as_str(), name_chars.as_str())
+                }
+                _ => false,
+            }
         }
-        *matched_groups
-            .borrow_mut()
-            .get_mut(group_index)
-            .expect("there should be a matched group") = &string[0..match_len];
-        Some(match_len)
     }
 }
 
-struct Expression {
-    matchers: Vec<Matcher>,
-    start_of_line: bool,
-    end_of_line: bool,
+/// Collects the files inside directories for `-r`. With `--follow` it remembers
+/// the directories it is in, so that a link to one of them doesn't make it loop forever.
+struct Walker<'o> {
+    options: &'o Options,
+    files: Vec<String>,
+    /// The real paths of the directory being walked and of the ones it is inside of.
+    ancestors: Vec<PathBuf>,
+    /// Whether all the directories could be read.
+    succeeded: bool,
 }
 
-impl Expression {
-    fn match_str(&self, input: &str) -> bool {
-        let mut offset = 0;
-        let mut matched_groups = RefCell::new(Vec::new());
-        for m in &self.matchers {
-            if offset >= input.len() {
-                return false;
+impl<'o> Walker<'o> {
+    fn new(options: &'o Options) -> Self {
+        Self {
+            options,
+            files: Vec::new(),
+            ancestors: Vec::new(),
+            succeeded: true,
+        }
+    }
+
+    /// Appends the files inside `directory` and its subdirectories to `files`,
+    /// skipping the subdirectories filtered out by `descends_into`, and symbolic links
+    /// unless `--follow` is given, in the order given by `--sort`. An empty `directory`
+    /// is the current one, whose entries are shown without a leading `./` like grep does.
+    fn walk(&mut self, directory: &Path) {
+        let read_from = match directory.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => directory,
+        };
+        if !self.options.follow {
+            return self.walk_entries(directory, read_from);
+        }
+        let real_path = match fs::canonicalize(read_from) {
+            Ok(real_path) => real_path,
+            Err(error) => return self.fail(read_from, &error),
+        };
+        // a directory reached twice on different paths is no loop, only one inside itself
+        if self.ancestors.contains(&real_path) {
+            let directory = read_from.display();
+            eprintln!("Warning: {directory}: recursive directory loop");
+            return;
+        }
+        self.ancestors.push(real_path);
+        self.walk_entries(directory, read_from);
+        self.ancestors.pop();
+    }
+
+    /// Walks the entries of `directory`, read from the path `read_from`.
+    fn walk_entries(&mut self, directory: &Path, read_from: &Path) {
+        let entries = match fs::read_dir(read_from) {
+            Ok(entries) => entries,
+            Err(error) => return self.fail(read_from, &error),
+        };
+        let mut listed = Vec::new();
+        for entry in entries {
+            match entry.and_then(|entry| Ok((entry.file_type()?, entry))) {
+                Ok(entry) => listed.push(entry),
+                Err(error) => self.fail(read_from, &error),
             }
-            let remaining_input = &input[offset..];
-            if let Some(shift) = m.match_some(remaining_input, &mut matched_groups) {
-                offset += shift;
-            } else {
-                return false;
+        }
+        // sorting the entries of every directory by name sorts the whole walk by path
+        if let Sort::Path = self.options.sort {
+            listed.sort_by_key(|(_, entry)| entry.file_name());
+        }
+        for (file_type, entry) in listed {
+            let path = directory.join(entry.file_name());
+            let file_type = match file_type.is_symlink() && self.options.follow {
+                true => match fs::metadata(&path) {
+                    Ok(metadata) => metadata.file_type(),
+                    Err(error) => {
+                        self.fail(&path, &error);
+                        continue;
+                    }
+                },
+                false => file_type,
+            };
+            if file_type.is_dir() {
+                if descends_into(self.options, &entry.file_name().to_string_lossy()) {
+                    self.walk(&path);
+                }
+            } else if file_type.is_file() {
+                self.files.push(path.display().to_string());
             }
         }
-        if self.till_end_of_string() {
-            offset >= input.len()
+    }
+
+    fn fail(&mut self, path: &Path, error: &io::Error) {
+        report_error(self.options, &path.display().to_string(), error);
+        self.succeeded = false;
+    }
+}
+
+/// Lists the files to search: the given ones, standard input without any,
+/// and with `-r` the files inside the given directories, the current one without any.
+/// Returns them together with whether all the directories could be read.
+fn input_files(options: &Options) -> (Vec<String>, bool) {
+    if !options.recursive {
+        return match options.files.is_empty() {
+            true => (vec!["-".to_owned()], true),
+            false => (options.files.clone(), true),
+        };
+    }
+    let mut walker = Walker::new(options);
+    if options.files.is_empty() {
+        walker.walk(Path::new(""));
+    }
+    for path in &options.files {
+        if Path::new(path).is_dir() {
+            walker.walk(Path::new(path));
         } else {
-            true
+            walker.files.push(path.clone());
+        }
+    }
+    (walker.files, walker.succeeded)
+}
+
+/// Prints an error about `path` unless `-s` is given. Only the message is silenced,
+/// the exit status still reports the error.
+fn report_error(options: &Options, path: &str, error: &io::Error) {
+    if !options.no_messages {
+        eprintln!("Error: {path}: {error}");
+    }
+}
+
+fn parse_number(flag: char, value: &str) -> Result<usize, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid number '{value}' for -{flag}"))
+}
+
+/// Keeps the last `capacity` lines that were not printed, with their line numbers,
+/// to be printed as leading context of the next match (`-B`).
+struct ContextBuffer {
+    lines: VecDeque<(usize, Line)>,
+    capacity: usize,
+}
+
+impl ContextBuffer {
+    fn new(capacity: usize) -> Self {
+        Self {
+            lines: VecDeque::with_capacity(capacity),
+            capacity,
         }
     }
 
-    fn from_start_of_string(&self) -> bool {
-        self.start_of_line
+    /// Remembers `line`, forgetting the oldest one when full so that the memory stays bounded.
+    fn push(&mut self, line_number: usize, line: Line) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+        self.lines.push_back((line_number, line));
     }
 
-    fn till_end_of_string(&self) -> bool {
-        self.end_of_line
+    fn drain(&mut self) -> impl Iterator<Item = (usize, Line)> + '_ {
+        self.lines.drain(..)
     }
 }
 
-struct Group {
-    start_index: usize,
-    alternative_index: Option<usize>,
+/// Writes the `separator`, `--` by default, that grep prints between groups of lines
+/// with context when `line_number` does not directly follow the last printed line.
+fn write_group_separator(
+    output: &mut impl Write,
+    separator: &str,
+    last_printed: &mut Option<usize>,
+    line_number: usize,
+) -> io::Result<()> {
+    if last_printed.is_some_and(|last| line_number > last + 1) {
+        writeln!(output, "{separator}")?;
+    }
+    *last_printed = Some(line_number);
+    Ok(())
 }
 
-impl TryFrom<&str> for Expression {
-    type Error = String;
-
-    fn try_from(value: &str) -> Result<Self, Self::Error> {
-        let mut pattern_index = 0;
-        let mut matchers = Vec::new();
-        let mut start_of_line = false;
-        let mut end_of_line = false;
-        let mut groups = Vec::new();
-        let mut group_count = 0;
-        while pattern_index < value.len() {
-            let remainder = &value[pattern_index..];
-            match Matcher::try_parse(remainder, matchers.last()) {
-                Some((Matcher::StartOfLine, offset)) => {
-                    start_of_line = true;
-                    pattern_index += offset;
-                }
-                Some((Matcher::EndOfLine, offset)) => {
-                    end_of_line = true;
-                    pattern_index += offset;
-                }
-                Some((Matcher::GroupStart, offset)) => {
-                    groups.push(Group {
-                        start_index: matchers.len(),
-                        alternative_index: None,
-                    });
-                    group_count += 1;
-                    pattern_index += offset;
-                }
-                Some((Matcher::Alteration, offset)) => {
-                    let group = groups
-                        .last_mut()
-                        .ok_or("Alteration not in group".to_owned())?;
+/// Writes the file name (when searching several files), the line number (`-n`)
+/// and the column (`--column`) before a printed line or match. They are followed
+/// by the field separator on matching lines and by the field context separator
+/// on context lines.
+fn write_prefix(
+    output: &mut impl Write,
+    options: &Options,
+    file_name: Option<&str>,
+    line_number: usize,
+    column: Option<usize>,
+    separator: &str,
+) -> io::Result<()> {
+    if let Some(file_name) = file_name {
+        write!(output, "{file_name}{separator}")?;
+    }
+    if options.line_number {
+        write!(output, "{line_number}{separator}")?;
+    }
+    if let Some(column) = column.filter(|_| options.column) {
+        write!(output, "{column}{separator}")?;
+    }
+    Ok(())
+}
 
-                    if group.alternative_index.is_some() {
-                        return Err("Double alteration in group".into());
-                    }
-                    group.alternative_index = Some(matchers.len());
-                    pattern_index += offset;
-                }
-                Some((Matcher::GroupEnd, offset)) => {
-                    let group = groups.pop().ok_or("Stray )".to_owned())?;
-                    let alternative_index =
-                        group.alternative_index.unwrap_or_else(|| matchers.len());
-                    let right = matchers.split_off(alternative_index);
-                    let left = matchers.split_off(group.start_index);
-                    matchers.push(Matcher::Group(left, right));
-                    pattern_index += offset;
-                }
-                Some((matcher @ Matcher::OneOrMore(_), offset))
-                | Some((matcher @ Matcher::ZeroOrOne(_), offset)) => {
-                    // TODO: pass previous as &mut to avoid copies
-                    matchers.pop();
-                    matchers.push(matcher);
-                    pattern_index += offset;
+/// Writes `line` with the `matches` spans wrapped into the highlighting escape codes.
+fn write_highlighted(
+    output: &mut impl Write,
+    line: &Line,
+    matches: &[(usize, usize)],
+) -> io::Result<()> {
+    let mut written = 0;
+    for &(start, end) in matches {
+        line.write(output, written, start)?;
+        write!(output, "{HIGHLIGHT_START}")?;
+        line.write(output, start, end)?;
+        write!(output, "{HIGHLIGHT_END}")?;
+        written = end;
+    }
+    line.write(output, written, line.text().len())?;
+    line.write_ending(output)
+}
+
+/// Converts the byte offset `start` into a 1-based character column of `line`.
+fn column(line: &str, start: usize) -> usize {
+    line[..start].chars().count() + 1
+}
+
+/// A line of input without its line ending. A line that is not valid UTF-8 is searched
+/// as decoded by [`DecodedBytes`] and printed back as its original bytes.
+struct Line {
+    bytes: DecodedBytes,
+    /// Whether the line ended with `\r\n`, which is printed back after it.
+    carriage_return: bool,
+}
+
+impl Line {
+    fn new(bytes: &[u8]) -> Self {
+        Self {
+            bytes: DecodedBytes::new(bytes),
+            carriage_return: false,
+        }
+    }
+
+    /// The text searched for matches.
+    fn text(&self) -> &str {
+        self.bytes.as_str()
+    }
+
+    /// The length of the line as read, in bytes.
+    fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Writes the part of the line from `start` to `end`, which are byte offsets into `text`.
+    fn write(&self, output: &mut impl Write, start: usize, end: usize) -> io::Result<()> {
+        output.write_all(&self.bytes.bytes(start, end))
+    }
+
+    /// Writes the whole line followed by its line ending.
+    fn write_line(&self, output: &mut impl Write) -> io::Result<()> {
+        self.write(output, 0, self.text().len())?;
+        self.write_ending(output)
+    }
+
+    /// Writes the line ending the line was read with, a newline if it had none.
+    fn write_ending(&self, output: &mut impl Write) -> io::Result<()> {
+        match self.carriage_return {
+            true => output.write_all(b"\r\n"),
+            false => writeln!(output),
+        }
+    }
+}
+
+/// Reads the lines of `input` without their `\n`, and without the `\r` before it
+/// unless `keep_carriage_returns`, so that `$` matches before them.
+/// A byte order mark is not part of the text, it is left out so that `^word` matches after it.
+fn lines(
+    mut input: impl BufRead,
+    keep_carriage_returns: bool,
+) -> impl Iterator<Item = io::Result<Line>> {
+    let mut first = true;
+    iter::from_fn(move || {
+        let mut line = Vec::new();
+        match input.read_until(b'\n', &mut line) {
+            Ok(0) => None,
+            Ok(_) => {
+                if mem::take(&mut first) && line.starts_with(BYTE_ORDER_MARK) {
+                    line.drain(..BYTE_ORDER_MARK.len());
                 }
-                Some((matcher @ Matcher::Backreference(n), offset)) => {
-                    if group_count < n {
-                        return Err("Invalid back reference".into());
+                let mut carriage_return = false;
+                if line.ends_with(b"\n") {
+                    line.pop();
+                    if !keep_carriage_returns && line.ends_with(b"\r") {
+                        line.pop();
+                        carriage_return = true;
                     }
-                    matchers.push(matcher);
-                    pattern_index += offset;
                 }
-                Some((matcher, offset)) => {
-                    matchers.push(matcher);
-                    pattern_index += offset;
-                }
-                None => return Err("Failed to parse a matcher".into()),
+                let mut line = Line::new(&line);
+                line.carriage_return = carriage_return;
+                Some(Ok(line))
             }
+            Err(error) => Some(Err(error)),
+        }
+    })
+}
+
+const BYTE_ORDER_MARK: &[u8] = "\u{feff}".as_bytes();
+
+/// Prints the lines of `input` selected by `pattern` (the matching ones, or the others
+/// with `-v`) together with their context, only the matches with `-o`, or only the number
+/// of selected lines with `-c` or of the matches in them with `--count-matches`,
+/// or only `file_name` if a line is selected with `-l`.
+/// Stops after `max_count` selected lines and their trailing context if given.
+/// The output is prefixed by `file_name` if given. Returns the number of selected lines.
+fn grep(
+    pattern: &Pattern,
+    options: &Options,
+    file_name: Option<&str>,
+    input: impl BufRead,
+    max_count: Option<usize>,
+    output: &mut impl Write,
+) -> io::Result<usize> {
+    let matching = options.field_separator.as_str();
+    let context = options.field_context_separator.as_str();
+    let highlight = options.highlight();
+    // `-l` prints nothing but the name, also with `-c`
+    let counting = (options.count || options.count_matches) && !options.files_with_matches;
+    // like grep, only the matches and no context are printed with `-o`
+    let (before_context, after_context) = if options.only_matching || counting {
+        (0, 0)
+    } else {
+        (options.before_context, options.after_context)
+    };
+    let with_context = before_context > 0 || after_context > 0;
+    let mut before = ContextBuffer::new(before_context);
+    // how many of the next lines are still trailing context of the last selected one
+    let mut after = 0;
+    // overlapping or adjacent context is printed once, other groups are separated by `--`
+    let mut last_printed = None;
+    let mut selected = 0;
+    let mut match_count = 0;
+    for (index, line) in lines(input, options.binary).enumerate() {
+        let limit_reached = max_count == Some(selected);
+        if limit_reached && after == 0 {
+            break;
         }
-        if !groups.is_empty() {
-            Err("Unclosed group".into())
+        let line = line?;
+        let line_number = index + 1;
+        if let Some(max) = options.max_line_length.filter(|max| line.len() > *max) {
+            // a skipped line is neither searched nor printed as context
+            let location = file_name.map_or(String::new(), |name| format!("{name}: "));
+            eprintln!("Warning: {location}skipped line {line_number}, longer than {max} bytes");
+            continue;
+        }
+        let first_match = match limit_reached {
+            true => None,
+            false => pattern.find_first(line.text()),
+        };
+        if limit_reached || first_match.is_some() == options.invert_match {
+            if after > 0 {
+                after -= 1;
+                write_group_separator(
+                    output,
+                    &options.context_separator,
+                    &mut last_printed,
+                    line_number,
+                )?;
+                write_prefix(output, options, file_name, line_number, None, context)?;
+                line.write_line(output)?;
+            } else {
+                before.push(line_number, line);
+            }
+            continue;
+        }
+        selected += 1;
+        if options.files_with_matches {
+            // the first selected line is enough to list the file, the rest is not read
+            if let Some(file_name) = file_name {
+                writeln!(output, "{file_name}")?;
+            }
+            return Ok(selected);
+        }
+        if options.count_matches && first_match.is_some() {
+            match_count += pattern.matches(line.text()).len();
+        }
+        if counting {
+            continue;
+        }
+        if options.only_matching {
+            // inverted selected lines have no matches to print
+            let matches = match first_match {
+                Some(_) => pattern.matches(line.text()),
+                None => Vec::new(),
+            };
+            for (start, end) in matches {
+                write_prefix(
+                    output,
+                    options,
+                    file_name,
+                    line_number,
+                    Some(column(line.text(), start)),
+                    matching,
+                )?;
+                if highlight {
+                    write!(output, "{HIGHLIGHT_START}")?;
+                    line.write(output, start, end)?;
+                    write!(output, "{HIGHLIGHT_END}")?;
+                } else {
+                    line.write(output, start, end)?;
+                }
+                writeln!(output)?;
+            }
         } else {
-            Ok(Self {
-                matchers,
-                start_of_line,
-                end_of_line,
-            })
+            for (context_number, context_line) in before.drain() {
+                write_group_separator(
+                    output,
+                    &options.context_separator,
+                    &mut last_printed,
+                    context_number,
+                )?;
+                write_prefix(output, options, file_name, context_number, None, context)?;
+                context_line.write_line(output)?;
+            }
+            if with_context {
+                write_group_separator(
+                    output,
+                    &options.context_separator,
+                    &mut last_printed,
+                    line_number,
+                )?;
+                after = after_context;
+            }
+            let column = first_match.map(|(start, _)| column(line.text(), start));
+            write_prefix(output, options, file_name, line_number, column, matching)?;
+            if highlight && first_match.is_some() {
+                write_highlighted(output, &line, &pattern.matches(line.text()))?;
+            } else {
+                line.write_line(output)?;
+            }
         }
     }
+    if counting {
+        if let Some(file_name) = file_name {
+            write!(output, "{file_name}{matching}")?;
+        }
+        let count = match options.count_matches {
+            true => match_count,
+            false => selected,
+        };
+        writeln!(output, "{count}")?;
+    }
+    Ok(selected)
 }
 
-fn match_pattern(input_line: &str, expression: &Expression) -> bool {
-    if input_line.is_empty() {
-        return false;
-    }
-    let mut input_index = 0;
-    while input_index < input_line.len() {
-        let remainder = &input_line[input_index..];
-        if expression.match_str(remainder) {
-            return true;
-        } else if expression.from_start_of_string() {
-            return false;
+/// Searches every file listed by `input_files`.
+/// `-m` limits the number of selected lines over all of them.
+/// Returns whether any line was selected and whether all the files could be read.
+fn grep_files(pattern: &Pattern, options: &Options, output: &mut impl Write) -> (bool, bool) {
+    let (files, mut succeeded) = input_files(options);
+    let with_file_names = files.len() > 1 || options.recursive || options.files_with_matches;
+    let mut selected = 0;
+    for path in &files {
+        let remaining = options.max_count.map(|max| max - selected);
+        if remaining == Some(0) {
+            break;
+        }
+        let file_name = match path.as_str() {
+            "-" => "(standard input)",
+            path => path,
+        };
+        let file_name = with_file_names.then_some(file_name);
+        let result = if path == "-" {
+            grep(
+                pattern,
+                options,
+                file_name,
+                io::stdin().lock(),
+                remaining,
+                output,
+            )
         } else {
-            input_index += 1;
+            File::open(path).and_then(|file| {
+                let input = BufReader::new(file);
+                grep(pattern, options, file_name, input, remaining, output)
+            })
+        };
+        match result {
+            Ok(file_selected) => selected += file_selected,
+            Err(error) => {
+                report_error(options, path, &error);
+                succeeded = false;
+            }
         }
     }
-    false
+    (selected > 0, succeeded)
 }
 
-// Usage: echo <input_text> | your_program.sh -E <pattern>
+// Usage: your_program.sh [OPTION]... <pattern> [FILE]...
+//    or: your_program.sh [OPTION]... (-e <pattern> | -f <pattern file>)... [FILE]...
+// e.g. `-E|-F`, `-i`, `-w`, `-x`, `-v`, `-c`, `--count-matches`, `-l`, `-s`/`--no-messages`,
+// `-U`/`--binary`, `-o`, `-n`, `--column`, `--color[=WHEN]`, `-A|-B|-C <num>`,
+// `-m <num>`/`--max-count=<num>`, `--field-separator=SEP`, `--field-context-separator=SEP`,
+// `--context-separator=SEP`, `--max-line-length=<num>`, `-r`, `-S`/`--follow`,
+// `--exclude-dir=GLOB`, `--include-dir=GLOB`, `--sort=none|path`
 fn main() {
-    // You can use print statements as follows for debugging, they'll be visible when running tests.
-    println!("Logs from your program will appear here!");
+    let options = Options::parse(env::args().skip(1));
+    let (options, pattern) = match options.and_then(|o| o.compile().map(|p| (o, p))) {
+        Ok(compiled) => compiled,
+        Err(error) => {
+            eprintln!("Error: {error}");
+            process::exit(2)
+        }
+    };
 
-    if env::args().nth(1).unwrap() != "-E" {
-        println!("Expected first argument to be '-E'");
-        process::exit(1);
+    let mut output = io::stdout().lock();
+    // like grep, exit with 0 if a line matched, 1 if none did and 2 on errors
+    match grep_files(&pattern, &options, &mut output) {
+        (_, false) => process::exit(2),
+        (true, true) => process::exit(0),
+        (false, true) => process::exit(1),
     }
+}
 
-    let pattern = env::args().nth(2).unwrap();
-    let mut input_line = String::new();
-
-    io::stdin().read_line(&mut input_line).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::{ContextBuffer, Line};
 
-    match Expression::try_from(pattern.as_ref()) {
-        Ok(expression) => {
-            if match_pattern(&input_line, &expression) {
-                process::exit(0)
-            } else {
-                process::exit(1)
-            }
-        }
-        Err(error) => {
-            eprintln!("Error: {error}");
-            process::exit(1)
+    #[test]
+    fn context_buffer_keeps_the_last_lines() {
+        let mut before = ContextBuffer::new(2);
+        for line_number in 1..=1_000 {
+            before.push(line_number, Line::new(line_number.to_string().as_bytes()));
+            assert!(before.lines.len() <= 2);
         }
+        let lines: Vec<_> = before
+            .drain()
+            .map(|(number, line)| (number, line.text().to_owned()))
+            .collect();
+        assert_eq!(lines, [(999, "999".to_owned()), (1_000, "1000".to_owned())]);
+        assert_eq!(before.drain().count(), 0);
+
+        let mut none = ContextBuffer::new(0);
+        none.push(1, Line::new(b"line"));
+        assert_eq!(none.drain().count(), 0);
     }
 }
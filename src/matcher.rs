@@ -0,0 +1,655 @@
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub(crate) enum Matcher {
+    StartOfLine,
+    EndOfLine,
+    /// `^` in multiline mode, at the start of the input or after a newline.
+    StartOfAnyLine,
+    /// `$` in multiline mode, at the end of the input or before a newline.
+    EndOfAnyLine,
+    /// `\b`, between a word character and something else, which is never inside
+    /// a character followed by combining marks.
+    WordBoundary,
+    WordChar,
+    Digit,
+    PositiveCharGroup(Vec<ClassMember>),
+    /// `[^...]`, any character that is in none of the members, except a newline
+    /// like in grep, where it never matches across lines.
+    NegativeCharGroup(Vec<ClassMember>),
+    Literal(char),
+    OneOrMore(Box<Matcher>),
+    ZeroOrMore(Box<Matcher>),
+    ZeroOrOne(Box<Matcher>),
+    /// `{min,max}`, from `min` to `max` repetitions, any number above `min` without `max`.
+    Repeat {
+        matcher: Box<Matcher>,
+        min: usize,
+        max: Option<usize>,
+    },
+    /// `.`, any character but a newline.
+    Wildcard,
+    /// `.` when it also matches newlines.
+    AnyChar,
+    /// A single character matcher that also accepts the other case of a character.
+    IgnoreCase(Box<Matcher>),
+    GroupStart,
+    GroupEnd,
+    Alteration,
+    Quantifier(Quantifier),
+    /// `(?<name>` or `(?P<name>` while parsing, opens a capturing group with a name.
+    NamedGroupStart(String),
+    /// `(?i)` while parsing, the flags to turn on for the rest of the enclosing group.
+    SetFlags {
+        ignore_case: bool,
+    },
+    /// `(?i:` while parsing, opens a non-capturing group with the flags turned on inside.
+    FlagGroupStart {
+        ignore_case: bool,
+    },
+    /// A group with its alternatives and, if it captures, its index in the order
+    /// of opening parentheses. The right alternative is only there after a `|`,
+    /// an empty one matches the empty string like in `(a|)b`.
+    Group {
+        left: Vec<Matcher>,
+        right: Option<Vec<Matcher>>,
+        index: Option<usize>,
+    },
+    Backreference(usize),
+}
+
+/// A `+`, `*`, `?` or `{min,max}` while parsing, before it is applied to the previous matcher.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Quantifier {
+    OneOrMore,
+    ZeroOrMore,
+    ZeroOrOne,
+    Repeat { min: usize, max: Option<usize> },
+}
+
+impl Quantifier {
+    /// Wraps `matcher` into the quantified matcher, without copying it.
+    pub(crate) fn apply(self, matcher: Matcher) -> Matcher {
+        match self {
+            Self::OneOrMore => Matcher::OneOrMore(Box::new(matcher)),
+            Self::ZeroOrMore => Matcher::ZeroOrMore(Box::new(matcher)),
+            Self::ZeroOrOne => Matcher::ZeroOrOne(Box::new(matcher)),
+            Self::Repeat { min, max } => Matcher::Repeat {
+                matcher: Box::new(matcher),
+                min,
+                max,
+            },
+        }
+    }
+}
+
+/// A single entry of a `[...]` character group.
+#[derive(Debug, Clone)]
+pub(crate) enum ClassMember {
+    Char(char),
+    Range(char, char),
+    Digit,
+    WordChar,
+    Alpha,
+    Alnum,
+    Space,
+}
+
+impl ClassMember {
+    fn contains(&self, c: char) -> bool {
+        match self {
+            Self::Char(member) => *member == c,
+            Self::Range(first, last) => (*first..=*last).contains(&c),
+            Self::Digit => is_digit(c),
+            Self::WordChar => is_word_char(c),
+            Self::Alpha => c.is_ascii_alphabetic(),
+            Self::Alnum => c.is_ascii_alphanumeric(),
+            Self::Space => c.is_ascii_whitespace() || c == '\x0b',
+        }
+    }
+
+    /// Parses the members of a character group up to the closing `]`,
+    /// returns them together with the length consumed, including the `]`.
+    fn parse_group(pattern: &str) -> Result<(Vec<Self>, usize), String> {
+        let mut members = Vec::new();
+        let mut index = 0;
+        while index < pattern.len() {
+            if pattern[index..].starts_with(']') {
+                return Ok((members, index + 1));
+            }
+            let (mut member, length) = Self::parse_member(&pattern[index..])?;
+            index += length;
+            // `-` between two characters makes a range, otherwise it is taken literally
+            let range_end = pattern[index..]
+                .strip_prefix('-')
+                .filter(|end| !end.is_empty() && !end.starts_with(']'));
+            if let (Self::Char(first), Some(range_end)) = (&member, range_end) {
+                let (Self::Char(last), length) = Self::parse_member(range_end)? else {
+                    return Err("Invalid range end in character group".into());
+                };
+                if last < *first {
+                    return Err(format!("Invalid range {first}-{last} in character group"));
+                }
+                member = Self::Range(*first, last);
+                index += 1 + length;
+            }
+            members.push(member);
+        }
+        Err("Unclosed character group".into())
+    }
+
+    fn parse_member(pattern: &str) -> Result<(Self, usize), String> {
+        if let Some(class) = pattern.strip_prefix("[:") {
+            let end = class.find(":]").ok_or("Unclosed character class")?;
+            let member = match &class[..end] {
+                "digit" => Self::Digit,
+                "alpha" => Self::Alpha,
+                "alnum" => Self::Alnum,
+                "space" => Self::Space,
+                name => return Err(format!("Unknown character class [:{name}:]")),
+            };
+            Ok((member, end + 4))
+        } else if let Some(escaped) = pattern.strip_prefix('\\') {
+            let c = escaped.chars().next().ok_or("Unclosed character group")?;
+            let member = match c {
+                'd' => Self::Digit,
+                'w' => Self::WordChar,
+                c => Self::Char(control_char(c).unwrap_or(c)),
+            };
+            Ok((member, 1 + c.len_utf8()))
+        } else {
+            let c = pattern.chars().next().ok_or("Unclosed character group")?;
+            Ok((Self::Char(c), c.len_utf8()))
+        }
+    }
+}
+
+/// The control character written by the escape `\c`: `\t` for a tab, `\n` for a newline
+/// and `\r` for a carriage return.
+fn control_char(c: char) -> Option<char> {
+    match c {
+        't' => Some('\t'),
+        'n' => Some('\n'),
+        'r' => Some('\r'),
+        _ => None,
+    }
+}
+
+/// The escape `\c` of a control character, the reverse of `control_char`.
+fn control_escape(c: char) -> Option<char> {
+    match c {
+        '\t' => Some('t'),
+        '\n' => Some('n'),
+        '\r' => Some('r'),
+        _ => None,
+    }
+}
+
+/// Whether `c` has a meaning of its own in a pattern outside of character groups,
+/// so that it has to be escaped as `\c` to be matched literally.
+fn is_meta_char(c: char) -> bool {
+    "\\^$.|?*+()[]{}".contains(c)
+}
+
+/// `c` together with its lowercase and uppercase forms, as far as they are single characters.
+fn case_variants(c: char) -> impl Iterator<Item = char> {
+    // a form of several characters like the uppercase `SS` of `ß` can't match a single one
+    let single = |chars: &mut dyn ExactSizeIterator<Item = char>| match chars.len() {
+        1 => chars.next(),
+        _ => None,
+    };
+    let lowercase = single(&mut c.to_lowercase());
+    let uppercase = single(&mut c.to_uppercase());
+    std::iter::once(c).chain(lowercase).chain(uppercase)
+}
+
+fn is_digit(c: char) -> bool {
+    c.is_ascii_digit()
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_lowercase() || c.is_ascii_uppercase() || c == '_'
+}
+
+/// Whether `position` of `input` is a word boundary for `\b`. Unlike for `\w`, letters and
+/// digits of any script are word characters. A combining mark belongs to the character before
+/// it, so there is no boundary in front of it and it is skipped when looking backwards:
+/// `e\u{301}` (an `e` with an acute accent) is a single word character.
+fn is_word_boundary(input: &str, position: usize) -> bool {
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let after = input[position..].chars().next();
+    if after.is_some_and(is_combining_mark) {
+        return false;
+    }
+    let before = input[..position]
+        .chars()
+        .rev()
+        .find(|c| !is_combining_mark(*c));
+    is_word(before) != is_word(after)
+}
+
+/// Approximates whether `c` extends the grapheme cluster of the character before it:
+/// the blocks of combining diacritical marks, variation selectors and the zero width joiner.
+fn is_combining_mark(c: char) -> bool {
+    matches!(
+        c,
+        '\u{300}'..='\u{36f}'
+            | '\u{1ab0}'..='\u{1aff}'
+            | '\u{1dc0}'..='\u{1dff}'
+            | '\u{200d}'
+            | '\u{20d0}'..='\u{20ff}'
+            | '\u{fe00}'..='\u{fe0f}'
+            | '\u{fe20}'..='\u{fe2f}'
+    )
+}
+
+impl Matcher {
+    /// Checks a zero-width matcher like `^` or `$` at `position` of `input`.
+    /// The whole input is needed (not only the remainder) to evaluate anchors.
+    pub(crate) fn is_match_at(&self, input: &str, position: usize) -> bool {
+        match self {
+            Self::StartOfLine => position == 0,
+            Self::EndOfLine => position == input.len(),
+            Self::StartOfAnyLine => position == 0 || input[..position].ends_with('\n'),
+            Self::EndOfAnyLine => position == input.len() || input[position..].starts_with('\n'),
+            Self::WordBoundary => is_word_boundary(input, position),
+            _ => false,
+        }
+    }
+
+    /// Checks a single character matcher against `c`.
+    pub(crate) fn match_char(&self, c: char) -> bool {
+        match self {
+            Self::WordChar => is_word_char(c),
+            Self::Digit => is_digit(c),
+            Self::PositiveCharGroup(g) => g.iter().any(|m| m.contains(c)),
+            Self::NegativeCharGroup(g) => c != '\n' && !g.iter().any(|m| m.contains(c)),
+            Self::Literal(l) => *l == c,
+            Self::Wildcard => c != '\n',
+            Self::AnyChar => true,
+            Self::IgnoreCase(matcher) => match &**matcher {
+                // `[^a]` rejects `A` too, so none of the cases may be in the group
+                Self::NegativeCharGroup(g) => {
+                    c != '\n' && !case_variants(c).any(|c| g.iter().any(|m| m.contains(c)))
+                }
+                matcher => case_variants(c).any(|c| matcher.match_char(c)),
+            },
+            _ => false,
+        }
+    }
+
+    /// Parses `(?<name>` or `(?P<name>`, where the name is made of ASCII letters, digits
+    /// and `_` and doesn't start with a digit.
+    fn parse_named_group(pattern: &str) -> Option<Result<(Self, usize), String>> {
+        let rest = pattern
+            .strip_prefix("(?<")
+            .or_else(|| pattern.strip_prefix("(?P<"))?;
+        let Some(end) = rest.find('>') else {
+            return Some(Err("Unclosed group name".into()));
+        };
+        let name = &rest[..end];
+        let valid = name.chars().next().is_some_and(|c| !c.is_ascii_digit())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Some(Err(format!("Invalid group name '{name}'")));
+        }
+        let length = pattern.len() - rest.len() + end + 1;
+        Some(Ok((Self::NamedGroupStart(name.into()), length)))
+    }
+
+    /// Parses `(?flags)` or `(?flags:`, where `i` is the only flag so far.
+    fn parse_flags(pattern: &str) -> Option<Result<(Self, usize), String>> {
+        let flags = pattern.strip_prefix("(?")?;
+        let mut ignore_case = false;
+        for (index, c) in flags.char_indices() {
+            match c {
+                'i' => ignore_case = true,
+                ')' => return Some(Ok((Self::SetFlags { ignore_case }, index + 3))),
+                ':' => return Some(Ok((Self::FlagGroupStart { ignore_case }, index + 3))),
+                c => return Some(Err(format!("Unknown flag '{c}' in group"))),
+            }
+        }
+        Some(Err("Unclosed group".into()))
+    }
+
+    fn parse_backreference(pattern: &str) -> Option<(usize, usize)> {
+        if !pattern.starts_with("\\") {
+            return None;
+        }
+        let number_size = pattern
+            .chars()
+            .skip(1)
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        if number_size == 0 {
+            return None;
+        }
+        // too many digits to hold the number are no group either, which the parser reports
+        let number = pattern[1..=number_size].parse().unwrap_or(usize::MAX);
+        Some((number, number_size + 1))
+    }
+
+    pub(crate) fn try_parse(pattern: &str) -> Result<(Self, usize), String> {
+        if pattern.starts_with("^") {
+            Ok((Self::StartOfLine, 1))
+        } else if pattern.starts_with("$") {
+            Ok((Self::EndOfLine, 1))
+        } else if pattern.starts_with("\\d") {
+            Ok((Self::Digit, 2))
+        } else if pattern.starts_with("\\w") {
+            Ok((Self::WordChar, 2))
+        } else if pattern.starts_with("\\b") {
+            Ok((Self::WordBoundary, 2))
+        } else if let Some(c) = pattern
+            .strip_prefix('\\')
+            .and_then(|escaped| escaped.chars().next())
+            .and_then(control_char)
+        {
+            Ok((Self::Literal(c), 2))
+        } else if let Some(c) = pattern
+            .strip_prefix('\\')
+            .and_then(|escaped| escaped.chars().next())
+            .filter(|c| is_meta_char(*c))
+        {
+            Ok((Self::Literal(c), 2))
+        } else if let Some((number, length)) = Self::parse_backreference(pattern) {
+            Ok((Self::Backreference(number), length))
+        } else if let Some(group) = pattern.strip_prefix("[^") {
+            ClassMember::parse_group(group)
+                .map(|(members, length)| (Self::NegativeCharGroup(members), length + 2))
+        } else if let Some(group) = pattern.strip_prefix("[") {
+            ClassMember::parse_group(group)
+                .map(|(members, length)| (Self::PositiveCharGroup(members), length + 1))
+        } else if let Some(repetition) = Self::parse_repetition(pattern) {
+            repetition
+        } else if pattern.starts_with("+") {
+            Ok((Self::Quantifier(Quantifier::OneOrMore), 1))
+        } else if pattern.starts_with("*") {
+            Ok((Self::Quantifier(Quantifier::ZeroOrMore), 1))
+        } else if pattern.starts_with("?") {
+            Ok((Self::Quantifier(Quantifier::ZeroOrOne), 1))
+        } else if pattern.starts_with(".") {
+            Ok((Self::Wildcard, 1))
+        } else if let Some(group) = Self::parse_named_group(pattern) {
+            group
+        } else if let Some(flags) = Self::parse_flags(pattern) {
+            flags
+        } else if pattern.starts_with("(") {
+            Ok((Self::GroupStart, 1))
+        } else if pattern.starts_with(")") {
+            Ok((Self::GroupEnd, 1))
+        } else if pattern.starts_with("|") {
+            Ok((Self::Alteration, 1))
+        } else {
+            let c = pattern.chars().next().ok_or("Failed to parse a matcher")?;
+            Ok((Self::Literal(c), c.len_utf8()))
+        }
+    }
+
+    /// Parses `{min,max}`, `{min,}`, `{,max}` or `{count}`. A `{` that starts nothing of these
+    /// is taken literally.
+    fn parse_repetition(pattern: &str) -> Option<Result<(Self, usize), String>> {
+        let rest = pattern.strip_prefix('{')?;
+        let end = rest.find('}')?;
+        let bounds = &rest[..end];
+        let is_number = |bound: &str| bound.bytes().all(|b| b.is_ascii_digit());
+        let (min, max) = match bounds.split_once(',') {
+            Some((min, max)) => (min, Some(max)),
+            None => (bounds, None),
+        };
+        if bounds.is_empty() || bounds == "," || !is_number(min) || !max.map_or(true, is_number) {
+            return None;
+        }
+        // a count too large to hold can't be reached anyway
+        let parse = |bound: &str| bound.parse().unwrap_or(usize::MAX);
+        let min_count = match min {
+            "" => 0,
+            min => parse(min),
+        };
+        let max_count = match max {
+            None => Some(min_count),
+            Some("") => None,
+            Some(max) => Some(parse(max)),
+        };
+        if max_count.is_some_and(|max| max < min_count) {
+            return Some(Err(format!("Invalid repetition {{{bounds}}}")));
+        }
+        let quantifier = Quantifier::Repeat {
+            min: min_count,
+            max: max_count,
+        };
+        Some(Ok((Self::Quantifier(quantifier), end + 2)))
+    }
+
+    /// Whether the pattern written by `write_pattern` starts with a digit,
+    /// which would be read as part of a backreference right before it.
+    fn starts_with_digit(&self) -> bool {
+        match self {
+            Self::Literal(c) => c.is_ascii_digit(),
+            Self::OneOrMore(matcher)
+            | Self::ZeroOrMore(matcher)
+            | Self::ZeroOrOne(matcher)
+            | Self::Repeat { matcher, .. } => matcher.starts_with_digit(),
+            _ => false,
+        }
+    }
+
+    /// Whether the matcher always consumes exactly one character.
+    pub(crate) fn is_single_char(&self) -> bool {
+        matches!(
+            self,
+            Self::WordChar
+                | Self::Digit
+                | Self::PositiveCharGroup(_)
+                | Self::NegativeCharGroup(_)
+                | Self::Literal(_)
+                | Self::Wildcard
+                | Self::AnyChar
+                | Self::IgnoreCase(_)
+        )
+    }
+
+    /// Whether a `+`, `*` or `?` may follow the matcher. Anchors match no characters,
+    /// so repeating them means nothing.
+    pub(crate) fn is_quantifiable(&self) -> bool {
+        !matches!(
+            self,
+            Self::StartOfLine
+                | Self::EndOfLine
+                | Self::StartOfAnyLine
+                | Self::EndOfAnyLine
+                | Self::WordBoundary
+        )
+    }
+
+    /// Makes a single character matcher case-insensitive, other matchers are left as they are.
+    /// `.` accepts both cases already and stays as it is, so that `dot_matches_newline`
+    /// still finds it.
+    pub(crate) fn ignore_case(&mut self) {
+        if self.is_single_char()
+            && !matches!(self, Self::IgnoreCase(_) | Self::Wildcard | Self::AnyChar)
+        {
+            let matcher = std::mem::replace(self, Self::Wildcard);
+            *self = Self::IgnoreCase(Box::new(matcher));
+        }
+    }
+
+    /// Estimates the memory taken by this matcher in bytes, including what it owns on the heap.
+    pub(crate) fn approximate_size(&self) -> usize {
+        let owned = match self {
+            Self::PositiveCharGroup(members) | Self::NegativeCharGroup(members) => {
+                members.len() * std::mem::size_of::<ClassMember>()
+            }
+            Self::OneOrMore(matcher)
+            | Self::ZeroOrMore(matcher)
+            | Self::ZeroOrOne(matcher)
+            | Self::Repeat { matcher, .. }
+            | Self::IgnoreCase(matcher) => matcher.approximate_size(),
+            Self::Group { left, right, .. } => left
+                .iter()
+                .chain(right.iter().flatten())
+                .map(Self::approximate_size)
+                .sum(),
+            _ => 0,
+        };
+        std::mem::size_of::<Self>() + owned
+    }
+
+    /// Calls `f` with this matcher and with every matcher nested in it.
+    pub(crate) fn visit_mut(&mut self, f: &mut impl FnMut(&mut Matcher)) {
+        f(self);
+        match self {
+            Self::OneOrMore(matcher)
+            | Self::ZeroOrMore(matcher)
+            | Self::ZeroOrOne(matcher)
+            | Self::Repeat { matcher, .. } => matcher.visit_mut(f),
+            Self::Group { left, right, .. } => left
+                .iter_mut()
+                .chain(right.iter_mut().flatten())
+                .for_each(|matcher| matcher.visit_mut(f)),
+            _ => {}
+        }
+    }
+}
+
+impl ClassMember {
+    fn write_pattern(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let write_char = |f: &mut fmt::Formatter, c: char| match c {
+            // `[` could start a `[:class:]` and `^` negate the group
+            '\\' | ']' | '[' | '^' | '-' => write!(f, "\\{c}"),
+            c => match control_escape(c) {
+                Some(escape) => write!(f, "\\{escape}"),
+                None => write!(f, "{c}"),
+            },
+        };
+        match self {
+            Self::Char(c) => write_char(f, *c),
+            Self::Range(first, last) => {
+                write_char(f, *first)?;
+                write!(f, "-")?;
+                write_char(f, *last)
+            }
+            Self::Digit => write!(f, "\\d"),
+            Self::WordChar => write!(f, "\\w"),
+            Self::Alpha => write!(f, "[:alpha:]"),
+            Self::Alnum => write!(f, "[:alnum:]"),
+            Self::Space => write!(f, "[:space:]"),
+        }
+    }
+}
+
+impl Matcher {
+    /// Writes `matchers` back as a pattern that parses to the same matchers or to ones
+    /// that match the same, with the capture groups named by `names`.
+    pub(crate) fn write_sequence(
+        f: &mut fmt::Formatter,
+        matchers: &[Matcher],
+        names: &[Option<String>],
+    ) -> fmt::Result {
+        for (index, matcher) in matchers.iter().enumerate() {
+            let followed_by_digit = matchers
+                .get(index + 1)
+                .is_some_and(Matcher::starts_with_digit);
+            match matcher {
+                // `\1` followed by `0` would be read as `\10`
+                Self::Backreference(number) if followed_by_digit => write!(f, "(?:\\{number})")?,
+                matcher => matcher.write_pattern(f, names)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the matcher back as a pattern. The multiline anchors are written as `^` and `$`,
+    /// which only mean the same with `RegexBuilder::multiline`.
+    fn write_pattern(&self, f: &mut fmt::Formatter, names: &[Option<String>]) -> fmt::Result {
+        match self {
+            Self::StartOfLine | Self::StartOfAnyLine => write!(f, "^"),
+            Self::EndOfLine | Self::EndOfAnyLine => write!(f, "$"),
+            Self::WordBoundary => write!(f, "\\b"),
+            Self::WordChar => write!(f, "\\w"),
+            Self::Digit => write!(f, "\\d"),
+            Self::PositiveCharGroup(members) | Self::NegativeCharGroup(members) => {
+                match self {
+                    Self::NegativeCharGroup(_) => write!(f, "[^")?,
+                    _ => write!(f, "[")?,
+                }
+                for member in members {
+                    member.write_pattern(f)?;
+                }
+                write!(f, "]")
+            }
+            Self::Literal(c) if is_meta_char(*c) => write!(f, "\\{c}"),
+            Self::Literal(c) => match control_escape(*c) {
+                Some(escape) => write!(f, "\\{escape}"),
+                None => write!(f, "{c}"),
+            },
+            Self::OneOrMore(matcher)
+            | Self::ZeroOrMore(matcher)
+            | Self::ZeroOrOne(matcher)
+            | Self::Repeat { matcher, .. } => {
+                matcher.write_pattern(f, names)?;
+                match self {
+                    Self::OneOrMore(_) => write!(f, "+"),
+                    Self::ZeroOrMore(_) => write!(f, "*"),
+                    Self::Repeat { min, max, .. } => write_repetition(f, *min, *max),
+                    _ => write!(f, "?"),
+                }
+            }
+            Self::Wildcard => write!(f, "."),
+            Self::AnyChar => write!(f, "(?:.|\\n)"),
+            Self::IgnoreCase(matcher) => {
+                write!(f, "(?i:")?;
+                matcher.write_pattern(f, names)?;
+                write!(f, ")")
+            }
+            Self::GroupStart => write!(f, "("),
+            Self::GroupEnd => write!(f, ")"),
+            Self::Alteration => write!(f, "|"),
+            Self::Quantifier(Quantifier::OneOrMore) => write!(f, "+"),
+            Self::Quantifier(Quantifier::ZeroOrMore) => write!(f, "*"),
+            Self::Quantifier(Quantifier::ZeroOrOne) => write!(f, "?"),
+            Self::Quantifier(Quantifier::Repeat { min, max }) => write_repetition(f, *min, *max),
+            Self::NamedGroupStart(name) => write!(f, "(?<{name}>"),
+            Self::SetFlags { ignore_case } => match ignore_case {
+                true => write!(f, "(?i)"),
+                false => write!(f, "(?)"),
+            },
+            Self::FlagGroupStart { ignore_case } => match ignore_case {
+                true => write!(f, "(?i:"),
+                false => write!(f, "(?:"),
+            },
+            // these are written as a group of their own already
+            Self::Group {
+                left,
+                right,
+                index: None,
+            } if right.is_none()
+                && matches!(left.as_slice(), [Self::IgnoreCase(_) | Self::AnyChar]) =>
+            {
+                left[0].write_pattern(f, names)
+            }
+            Self::Group { left, right, index } => {
+                match index.map(|index| &names[index]) {
+                    Some(Some(name)) => write!(f, "(?<{name}>")?,
+                    Some(None) => write!(f, "(")?,
+                    None => write!(f, "(?:")?,
+                }
+                Self::write_sequence(f, left, names)?;
+                if let Some(right) = right {
+                    write!(f, "|")?;
+                    Self::write_sequence(f, right, names)?;
+                }
+                write!(f, ")")
+            }
+            Self::Backreference(number) => write!(f, "\\{number}"),
+        }
+    }
+}
+
+fn write_repetition(f: &mut fmt::Formatter, min: usize, max: Option<usize>) -> fmt::Result {
+    match max {
+        Some(max) if max == min => write!(f, "{{{min}}}"),
+        Some(max) => write!(f, "{{{min},{max}}}"),
+        None => write!(f, "{{{min},}}"),
+    }
+}
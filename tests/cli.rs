@@ -0,0 +1,374 @@
+use std::fs;
+use std::io::Write;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+
+/// Runs the program in `directory` with `args`, feeding it `input` on standard input.
+fn run_in(directory: &Path, args: &[&str], input: impl AsRef<[u8]>) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_codecrafters-grep"))
+        .args(args)
+        .current_dir(directory)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the program starts");
+    let mut stdin = child.stdin.take().expect("standard input is piped");
+    let input = input.as_ref().to_vec();
+    // written from a thread of its own, a large input would fill the pipe otherwise
+    let writer = thread::spawn(move || {
+        // the program may exit before it read everything, e.g. with `-l`
+        let _ = stdin.write_all(&input);
+    });
+    let output = child.wait_with_output().expect("the program runs");
+    writer.join().expect("the input is written");
+    output
+}
+
+/// A fresh directory for the files of a test, removed again when the test is done.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let directory = std::env::temp_dir().join(format!("codecrafters-grep-{name}"));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).expect("the directory is created");
+        Self(directory)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn run(args: &[&str], input: impl AsRef<[u8]>) -> Output {
+    run_in(&std::env::temp_dir(), args, input)
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).expect("the output is UTF-8")
+}
+
+/// Whether the program finds a match for `args` in `input`, as told by its exit status.
+fn matches(args: &[&str], input: &str) -> bool {
+    run(args, input).status.success()
+}
+
+#[test]
+fn fixed_strings_ignoring_case() {
+    assert!(matches(&["-Fi", "ABC"], "xabcy\n"));
+    assert!(!matches(&["-Fi", "ABC"], "xyz\n"));
+    assert!(!matches(&["-Fi", "ABC"], "A.C\n"));
+    assert!(matches(&["-F", "a.c"], "a.c\n"));
+    assert!(!matches(&["-F", "a.c"], "abc\n"));
+}
+
+#[test]
+fn before_context() {
+    let output = run(&["-B", "1", "b"], "a\nb\nc\nb\n");
+    assert_eq!(stdout(&output), "a\nb\nc\nb\n");
+    let output = run(&["b"], "a\nb\nc\n");
+    assert_eq!(stdout(&output), "b\n");
+}
+
+#[test]
+fn only_matching_with_line_and_column() {
+    let output = run(&["-o", "-n", "--column", "cat"], "x cat\nab cat cat\n");
+    assert_eq!(stdout(&output), "1:3:cat\n2:4:cat\n2:8:cat\n");
+}
+
+#[test]
+fn repeated_group_on_a_long_line() {
+    let line = "ab".repeat(100_000);
+    let output = run(&["-E", "(ab)+"], format!("{line}\n"));
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), format!("{line}\n"));
+
+    assert!(matches(&["-E", "(a|b)+c"], &format!("{line}c\n")));
+}
+
+#[test]
+fn color() {
+    let output = run(&["--color=always", "cat"], "a cat\nno\n");
+    assert_eq!(stdout(&output), "a \x1b[31mcat\x1b[0m\n");
+    let output = run(&["--color=never", "cat"], "a cat\nno\n");
+    assert_eq!(stdout(&output), "a cat\n");
+    // the output of the tests is a pipe
+    let output = run(&["--color", "cat"], "a cat\n");
+    assert_eq!(stdout(&output), "a cat\n");
+}
+
+#[test]
+fn separators() {
+    let directory = TempDir::new("separators");
+    fs::write(directory.join("file"), "a\nb\ncontent\nc\nd\ne\ncontent\n").unwrap();
+    fs::write(directory.join("other"), "nothing\n").unwrap();
+    let output = run_in(
+        &directory,
+        &["-n", "--field-separator=|", "content", "file", "other"],
+        "",
+    );
+    assert_eq!(stdout(&output), "file|3|content\nfile|7|content\n");
+
+    let output = run_in(
+        &directory,
+        &[
+            "-n",
+            "-B1",
+            "--field-context-separator=~",
+            "--context-separator===",
+            "content",
+            "file",
+        ],
+        "",
+    );
+    assert_eq!(stdout(&output), "2~b\n3:content\n==\n6~e\n7:content\n");
+
+    // `--` between the groups by default
+    let output = run_in(&directory, &["-B1", "content", "file"], "");
+    assert_eq!(stdout(&output), "b\ncontent\n--\ne\ncontent\n");
+}
+
+#[test]
+fn max_count() {
+    let input = "m1\nm2\nm3\nm4\nm5\nx\ny\n";
+    let output = run(&["-m", "2", "m"], input);
+    assert_eq!(stdout(&output), "m1\nm2\n");
+    let output = run(&["-m2", "-c", "m"], input);
+    assert_eq!(stdout(&output), "2\n");
+    let output = run(&["--max-count=1", "-v", "m"], input);
+    assert_eq!(stdout(&output), "x\n");
+}
+
+#[test]
+fn missing_file_without_messages() {
+    let output = run(&["-s", "x", "missing"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stderr.is_empty());
+    assert!(output.stdout.is_empty());
+
+    let output = run(&["x", "missing"], "");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(!output.stderr.is_empty());
+
+    // only errors about files are silenced, not warnings
+    let output = run(&["-s", "--max-line-length=2", "x"], "xxx\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Warning: skipped line 1, longer than 2 bytes\n"
+    );
+}
+
+#[test]
+fn recursive_skips_target_and_excluded_directories() {
+    let directory = TempDir::new("exclude-dir");
+    write_tree(
+        &directory,
+        &[
+            ("src/main.rs", "needle\n"),
+            ("target/debug/out", "needle\n"),
+            ("docs/a.txt", "needle\n"),
+            ("top.txt", "needle\n"),
+        ],
+    );
+    let output = run_in(&directory, &["-r", "--sort=path", "needle"], "");
+    assert_eq!(
+        stdout(&output),
+        "docs/a.txt:needle\nsrc/main.rs:needle\ntop.txt:needle\n"
+    );
+    let output = run_in(
+        &directory,
+        &["-r", "--sort=path", "--exclude-dir=d*", "needle"],
+        "",
+    );
+    assert_eq!(stdout(&output), "src/main.rs:needle\ntop.txt:needle\n");
+    // only the current directory searched by default is left out of the paths
+    let output = run_in(
+        &directory,
+        &["-r", "--sort=path", "needle", "./src", "."],
+        "",
+    );
+    assert_eq!(
+        stdout(&output),
+        "./src/main.rs:needle\n./docs/a.txt:needle\n./src/main.rs:needle\n./top.txt:needle\n"
+    );
+}
+
+/// Writes `files` with their contents under `directory`, creating the directories they are in.
+fn write_tree(directory: &Path, files: &[(&str, &str)]) {
+    for (path, contents) in files {
+        let path = directory.join(path);
+        fs::create_dir_all(path.parent().expect("the file is in a directory"))
+            .expect("the directory is created");
+        fs::write(path, contents).expect("the file is written");
+    }
+}
+
+#[test]
+fn overlong_lines_are_skipped() {
+    let input = format!("short a\n{} a\nok a\n", "x".repeat(50));
+    let output = run(&["--max-line-length=20", "a"], input);
+    assert_eq!(stdout(&output), "short a\nok a\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Warning: skipped line 2, longer than 20 bytes\n"
+    );
+}
+
+#[test]
+fn whole_words() {
+    let input = "the cat sat\nconcatenate\nThe CAT\n";
+    let output = run(&["-w", "cat"], input);
+    assert_eq!(stdout(&output), "the cat sat\n");
+    let output = run(&["-w", "-i", "-o", "cat"], input);
+    assert_eq!(stdout(&output), "cat\nCAT\n");
+    let output = run(&["-w", "-E", "a|ab"], "ab\n");
+    assert_eq!(stdout(&output), "ab\n");
+    // a shorter match at the same position can be the whole word
+    let output = run(&["-w", "-o", "-E", "a.|a"], "a b\n");
+    assert_eq!(stdout(&output), "a\n");
+}
+
+#[test]
+fn whole_lines() {
+    let input = "cat\ncats\ncat \n";
+    let output = run(&["-x", "cat"], input);
+    assert_eq!(stdout(&output), "cat\n");
+    let output = run(&["-xv", "cat"], input);
+    assert_eq!(stdout(&output), "cats\ncat \n");
+}
+
+#[test]
+fn byte_order_mark() {
+    let output = run(&["^word"], "\u{feff}word one\nword two\n");
+    assert_eq!(stdout(&output), "word one\nword two\n");
+}
+
+#[test]
+fn binary_keeps_carriage_returns() {
+    let output = run(&["-c", "abc$"], "abc\r\n");
+    assert_eq!(stdout(&output), "1\n");
+    let output = run(&["-U", "-c", "abc$"], "abc\r\n");
+    assert_eq!(stdout(&output), "0\n");
+}
+
+#[test]
+fn invalid_utf8_lines() {
+    let output = run(&["[0-9]+"], b"\xff\xfe bad\nplain 42\n");
+    assert_eq!(stdout(&output), "plain 42\n");
+    // the line is printed back byte for byte
+    let output = run(&["7"], b"\xff x 7\n");
+    assert_eq!(output.stdout, b"\xff x 7\n");
+    let output = run(&["café"], b"caf\xc3\xa9 \xff\n");
+    assert_eq!(output.stdout, b"caf\xc3\xa9 \xff\n");
+}
+
+#[test]
+fn count_matches() {
+    let output = run(&["--count-matches", r"\d+"], "a1b22c333\nx9\nnone\n");
+    assert_eq!(stdout(&output), "4\n");
+}
+
+#[test]
+fn only_matching_prints_the_longest_alternative() {
+    let output = run(&["-o", "-E", "a|ab|abc"], "abcabc\n");
+    assert_eq!(stdout(&output), "abc\nabc\n");
+
+    let output = run(&["-E", "a|ab|abc"], "abcabc\nxyz\n");
+    assert_eq!(stdout(&output), "abcabc\n");
+}
+
+#[test]
+fn sort_by_path() {
+    let directory = TempDir::new("sort");
+    write_tree(
+        &directory,
+        &[("b/2", "x\n"), ("a/1", "x\n"), ("c", "x\n"), ("a/0", "x\n")],
+    );
+    let output = run_in(&directory, &["-r", "--sort=path", "x"], "");
+    assert_eq!(stdout(&output), "a/0:x\na/1:x\nb/2:x\nc:x\n");
+}
+
+#[test]
+fn patterns_from_a_file() {
+    let directory = TempDir::new("pattern-file");
+    fs::write(directory.join("patterns"), "\\d+\ncat\n\n").unwrap();
+    let output = run_in(&directory, &["-f", "patterns"], "abc\n12\ncat\n\nx3\n");
+    assert_eq!(stdout(&output), "12\ncat\nx3\n");
+}
+
+#[test]
+fn several_patterns() {
+    let input = "one\ntwo\nthree\n";
+    let output = run(&["-e", "one", "-e", "three"], input);
+    assert_eq!(stdout(&output), "one\nthree\n");
+    let output = run(&["-v", "-e", "one", "-e", "three"], input);
+    assert_eq!(stdout(&output), "two\n");
+    let output = run(&["-c", "-e", "one", "-e", "three"], input);
+    assert_eq!(stdout(&output), "2\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn symbolic_links() {
+    use std::os::unix::fs::symlink;
+
+    let directory = TempDir::new("follow");
+    write_tree(&directory, &[("src/main.rs", "needle\n")]);
+    symlink("src", directory.join("link")).unwrap();
+    fs::create_dir(directory.join("sub")).unwrap();
+    symlink("..", directory.join("sub/up")).unwrap();
+
+    let output = run_in(&directory, &["-r", "--sort=path", "needle"], "");
+    assert_eq!(stdout(&output), "src/main.rs:needle\n");
+    // `src` is reached twice, which is no loop, while the loop through `sub/up` ends
+    let output = run_in(&directory, &["-rS", "--sort=path", "needle"], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "link/main.rs:needle\nsrc/main.rs:needle\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Warning: sub/up: recursive directory loop\n"
+    );
+}
+
+#[test]
+fn context() {
+    let input: String = (1..=12).map(|n| format!("{n}\n")).collect();
+    let output = run(&["-A1", "-e", "^3$", "-e", "^4$", "-e", "^10$"], &input);
+    assert_eq!(stdout(&output), "3\n4\n5\n--\n10\n11\n");
+    let output = run(&["-B2", "-n", "-e", "^5$", "-e", "^11$"], &input);
+    assert_eq!(stdout(&output), "3-3\n4-4\n5:5\n--\n9-9\n10-10\n11:11\n");
+    // the regions of `3` and `6` touch and merge
+    let output = run(&["-C1", "-e", "^3$", "-e", "^6$"], &input);
+    assert_eq!(stdout(&output), "2\n3\n4\n5\n6\n7\n");
+}
+
+#[test]
+fn carriage_returns() {
+    let output = run(&["dog$"], "hotdog\r\ncat\r\nmy dog\n");
+    assert_eq!(output.stdout, b"hotdog\r\nmy dog\n");
+}
+
+#[test]
+fn files_with_matches() {
+    let directory = TempDir::new("files-with-matches");
+    write_tree(
+        &directory,
+        &[("a", "hit\n"), ("b", "no\n"), ("c", "x\nhit\n")],
+    );
+    let output = run_in(&directory, &["-l", "hit", "a", "b", "c"], "");
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(stdout(&output), "a\nc\n");
+}
@@ -0,0 +1,571 @@
+use codecrafters_grep::{Expression, MatchKind, RegexBuilder};
+
+fn expression(pattern: &str) -> Expression {
+    Expression::try_from(pattern).expect("the pattern is valid")
+}
+
+#[test]
+fn empty_input() {
+    assert_eq!(expression("").find(""), Some((0, 0)));
+    assert_eq!(expression("^$").find(""), Some((0, 0)));
+    assert_eq!(expression("^").find(""), Some((0, 0)));
+    assert_eq!(expression("a").find(""), None);
+}
+
+#[test]
+fn empty_groups_and_alternatives_match_empty() {
+    assert_eq!(expression("(a|)b").find("b"), Some((0, 1)));
+    assert_eq!(expression("(a|)b").find("ab"), Some((0, 2)));
+    assert_eq!(expression("()b").find("b"), Some((0, 1)));
+    assert_eq!(
+        expression("()b").captures("b").and_then(|c| c.get(1)),
+        Some((0, 0))
+    );
+    // the `a` outside the group is still needed
+    assert_eq!(expression("a(|)b").find("b"), None);
+    assert_eq!(expression("a(|)b").find("ab"), Some((0, 2)));
+    assert_eq!(expression("(a|)b").to_string(), "(a|)b");
+}
+
+#[test]
+fn shorthand_class_in_a_negated_group() {
+    let pattern = expression(r"[^\d]");
+    assert_eq!(pattern.find("a"), Some((0, 1)));
+    assert_eq!(pattern.find("5"), None);
+    assert_eq!(pattern.find("12x"), Some((2, 3)));
+}
+
+#[test]
+fn anchors_match_only_at_the_ends() {
+    assert_eq!(expression("a$b").find("ab"), None);
+    assert_eq!(expression("a$b").find("a\nb"), None);
+    let pattern = expression("^a");
+    assert_eq!(pattern.find("aa"), Some((0, 1)));
+    assert_eq!(pattern.find("ba"), None);
+}
+
+#[test]
+fn remove_all_matches() {
+    assert_eq!(expression(r"\d").remove_all("a1b2c3"), "abc");
+}
+
+#[test]
+fn replace_with_groups() {
+    let pattern = expression(r"(\w+)@(\w+)");
+    assert_eq!(pattern.replace("bob@host", "$2.$1"), "host.bob");
+    assert_eq!(pattern.replace("mail bob@host", "<$0>"), "mail <bob@host>");
+    // only the first match is replaced, unlike with replace_all
+    assert_eq!(pattern.replace("a@b c@d", "$2.$1"), "b.a c@d");
+    assert_eq!(pattern.replace_all("a@b c@d", "$2.$1"), "b.a d.c");
+    assert_eq!(pattern.replace("no match", "$1"), "no match");
+}
+
+#[test]
+fn posix_classes() {
+    let class = |pattern: &str, input: &str| expression(pattern).find(input).is_some();
+    assert!(class("[[:digit:]]", "7"));
+    assert!(!class("[[:digit:]]", "x"));
+    assert!(class("[[:alpha:]]", "x"));
+    assert!(!class("[[:alpha:]]", "7"));
+    assert!(class("[[:alnum:]]", "x"));
+    assert!(class("[[:alnum:]]", "7"));
+    assert!(!class("[[:alnum:]]", "_"));
+    assert!(class("[[:space:]]", "\t"));
+    assert!(!class("[[:space:]]", "x"));
+
+    let pattern = expression("[[:digit:]a-f]+");
+    assert_eq!(pattern.find("xx12fe9g"), Some((2, 7)));
+    assert!(Expression::try_from("[[:bogus:]]").is_err());
+}
+
+#[test]
+fn split_keeping_the_captures() {
+    assert_eq!(
+        expression(r"(\d)").split_captures("a1b2c"),
+        ["a", "1", "b", "2", "c"]
+    );
+    assert_eq!(expression(r"\d").split("a1b2c"), ["a", "b", "c"]);
+}
+
+#[test]
+fn repeated_group_of_long_alternatives() {
+    let pattern = expression("(abcdefghij|klmnop)+z");
+    assert_eq!(pattern.find("xabcdefghijklmnopz"), Some((1, 18)));
+    // the group captures its last repetition
+    assert_eq!(
+        pattern
+            .captures("xabcdefghijklmnopz")
+            .and_then(|c| c.text(1)),
+        Some("klmnop")
+    );
+    assert_eq!(pattern.find("abcdefghij"), None);
+    assert_eq!(pattern.find("abcdefghiz"), None);
+}
+
+#[test]
+fn spans_are_byte_offsets() {
+    assert_eq!(expression("b").find("éb"), Some((2, 3)));
+    assert_eq!(expression(".b").find("éb"), Some((0, 3)));
+}
+
+#[test]
+fn backreferences_to_nested_groups() {
+    let pattern = expression(r"((a)(b))\2\3");
+    let captures = pattern.captures("abab").expect("the pattern matches");
+    assert_eq!(captures.text(1), Some("ab"));
+    assert_eq!(captures.text(2), Some("a"));
+    assert_eq!(captures.text(3), Some("b"));
+    assert_eq!(pattern.find("abba"), None);
+}
+
+#[test]
+fn repeated_group_on_a_long_input() {
+    let input = "ab".repeat(100_000);
+    assert_eq!(expression("(ab)+").find(&input), Some((0, input.len())));
+    assert_eq!(
+        expression("(ab)+").captures(&input).and_then(|c| c.get(1)),
+        Some((input.len() - 2, input.len()))
+    );
+    let input = format!("{input}c");
+    assert_eq!(expression("(a|b)+c").find(&input), Some((0, input.len())));
+    assert!(expression("(ab)+c").is_full_match(&input));
+}
+
+#[test]
+fn backreference_to_a_repeated_group() {
+    let pattern = expression(r"(\w)+\1");
+    assert_eq!(pattern.find("abb"), Some((0, 3)));
+    assert_eq!(pattern.captures("abb").and_then(|c| c.get(1)), Some((1, 2)));
+    assert_eq!(pattern.find("abc"), None);
+
+    let pattern = expression(r"(ab)+\1");
+    assert_eq!(pattern.find("ababab"), Some((0, 6)));
+    assert_eq!(pattern.find("xabab"), Some((1, 5)));
+    assert_eq!(pattern.find("ab"), None);
+    assert_eq!(pattern.find("abba"), None);
+}
+
+#[test]
+fn validate_patterns() {
+    assert_eq!(Expression::validate(r"(\w+)@\d+"), Ok(()));
+    assert!(Expression::validate("(a").is_err());
+    assert!(Expression::validate("a)").is_err());
+    assert!(Expression::validate(r"(a)\2").is_err());
+}
+
+#[test]
+fn top_level_alternatives() {
+    let pattern = expression("cat|dog");
+    assert_eq!(pattern.find("a cat"), Some((2, 5)));
+    assert_eq!(pattern.find("my dog"), Some((3, 6)));
+    assert_eq!(pattern.find("a cow"), None);
+
+    let pattern = expression("^cat|dog$");
+    assert_eq!(pattern.find("cat food"), Some((0, 3)));
+    assert_eq!(pattern.find("hotdog"), Some((3, 6)));
+    assert_eq!(pattern.find("a cat"), None);
+    assert_eq!(pattern.find("dogs"), None);
+}
+
+#[test]
+fn match_kinds() {
+    let pattern = expression("a|ab");
+    assert_eq!(
+        pattern.find_with("ab", MatchKind::LeftmostFirst),
+        Some((0, 1))
+    );
+    assert_eq!(
+        pattern.find_with("ab", MatchKind::LeftmostLongest),
+        Some((0, 2))
+    );
+}
+
+#[test]
+fn match_at_start_and_end() {
+    let pattern = expression(r"\d+");
+    assert!(pattern.is_match_at_end("abc123"));
+    assert!(!pattern.is_match_at_start("abc123"));
+    assert!(pattern.is_match_at_start("123abc"));
+    assert!(!pattern.is_match_at_end("123abc"));
+}
+
+#[test]
+fn control_escapes() {
+    assert_eq!(expression(r"a\tb").find("a\tb"), Some((0, 3)));
+    assert_eq!(expression(r"a\tb").find(r"a\tb"), None);
+    let pattern = expression(r"x[\t ]y");
+    assert_eq!(pattern.find("x\ty"), Some((0, 3)));
+    assert_eq!(pattern.find("x y"), Some((0, 3)));
+    assert_eq!(pattern.find("xty"), None);
+}
+
+#[test]
+fn dot_and_newlines() {
+    assert_eq!(expression("a.b").find("a\nb"), None);
+    let mut builder = RegexBuilder::new();
+    builder.dot_matches_newline(true);
+    let pattern = builder.build("a.b").expect("the pattern is valid");
+    assert_eq!(pattern.find("a\nb"), Some((0, 3)));
+    let pattern = builder.build("(a.)+").expect("the pattern is valid");
+    assert_eq!(pattern.find("a\na\n"), Some((0, 4)));
+}
+
+#[test]
+fn backreference_to_the_alternative_that_matched() {
+    let pattern = expression(r"(abc|a)(def)\1");
+    let captures = pattern.captures("adefa").expect("the pattern matches");
+    assert_eq!(captures.text(1), Some("a"));
+    assert_eq!(captures.text(2), Some("def"));
+    assert!(pattern.captures("abcdefa").is_none());
+}
+
+/// A small linear congruential generator, enough to make up patterns and inputs.
+struct Random(u64);
+
+impl Random {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) as usize % n
+    }
+}
+
+#[test]
+fn random_patterns_never_panic() {
+    let tokens = [
+        "a",
+        "b",
+        "é",
+        ".",
+        "(",
+        ")",
+        "|",
+        "+",
+        "?",
+        "*",
+        "^",
+        "$",
+        r"\1",
+        r"\2",
+        r"\99",
+        "[ab]",
+        "[^a]",
+        r"\d",
+        r"\w",
+        r"\b",
+        "{2}",
+        "{1,3}",
+        "{,2}",
+        "{3,1}",
+        "(?:",
+        "(?i)",
+        "(?i:",
+        "(?",
+        "[[:alpha:]]",
+        "[",
+        "]",
+        r"\",
+    ];
+    let alphabet = ['a', 'b', 'é', '1', 'A', ' ', '\n'];
+    let mut random = Random(289);
+    let mut builder = RegexBuilder::new();
+    // patterns that backtrack catastrophically give up rather than slow the test down
+    builder.max_steps(100_000);
+    // about a third of them are valid patterns
+    for _ in 0..1_000 {
+        let length = random.below(8);
+        let pattern: String = (0..length)
+            .map(|_| tokens[random.below(tokens.len())])
+            .collect();
+        let Ok(expression) = builder.build(&pattern) else {
+            continue;
+        };
+        let length = random.below(8);
+        let short: String = (0..length)
+            .map(|_| alphabet[random.below(alphabet.len())])
+            .collect();
+        let long = short.repeat(2_000);
+        for input in [short.as_str(), long.as_str()] {
+            let _ = expression.try_find(input);
+            let _ = expression.find_with(input, MatchKind::LeftmostLongest);
+            let _ = expression.captures(input).map(|c| c.get(2));
+            let _ = expression.split_captures(input);
+            let _ = expression.is_match_at_end(input);
+            let _ = expression.is_full_match(input);
+        }
+        // every position, including ones inside `é` and past the end
+        for position in 0..=short.len() + 1 {
+            let _ = expression.find_at(&short, position);
+        }
+    }
+}
+
+#[test]
+fn no_stale_captures_after_backtracking() {
+    // the inner group captured on the abandoned first alternative must be forgotten
+    let pattern = expression(r"((a)b|a)c\2");
+    assert_eq!(pattern.find("aca"), None);
+    assert_eq!(pattern.find("abca"), Some((0, 4)));
+
+    let pattern = expression(r"(a)?a\1");
+    assert_eq!(pattern.find("aa"), None);
+    assert_eq!(pattern.find("aaa"), Some((0, 3)));
+}
+
+#[test]
+fn captures_without_groups() {
+    let captures = expression(r"\d+")
+        .captures("ab12")
+        .expect("the pattern matches");
+    assert_eq!(captures.get(0), Some((2, 4)));
+    assert_eq!(captures.get(1), None);
+}
+
+#[test]
+fn find_only_accepted_matches() {
+    let input = "a b";
+    let followed_by_space = |_, end: usize| input[end..].starts_with(' ');
+    let pattern = expression("a.|a");
+    for kind in [MatchKind::LeftmostFirst, MatchKind::LeftmostLongest] {
+        let found = pattern.find_at_where(input, 0, kind, followed_by_space);
+        assert_eq!(found, Some((0, 1)), "{kind:?}");
+    }
+    let found = pattern.find_at_where("ab a ", 0, MatchKind::LeftmostLongest, |_, end| end > 2);
+    assert_eq!(found, Some((3, 5)));
+    // a literal pattern is searched for as a substring, and passed over all the same
+    let found =
+        expression("a").find_at_where("ab a", 0, MatchKind::LeftmostFirst, |start, _| start > 0);
+    assert_eq!(found, Some((3, 4)));
+}
+
+#[test]
+fn full_match() {
+    let pattern = expression(r"\d+");
+    assert!(pattern.is_full_match("123"));
+    assert!(!pattern.is_full_match("123abc"));
+    assert!(!pattern.is_full_match(""));
+}
+
+#[test]
+fn builder_ignore_case() {
+    let pattern = r"Hello [w]orld\d";
+    let sensitive = RegexBuilder::new()
+        .build(pattern)
+        .expect("the pattern is valid");
+    let insensitive = RegexBuilder::new()
+        .ignore_case(true)
+        .build(pattern)
+        .expect("the pattern is valid");
+    assert_eq!(sensitive.find("HELLO WORLD1"), None);
+    assert_eq!(insensitive.find("HELLO WORLD1"), Some((0, 12)));
+    assert_eq!(sensitive.find("Hello world1"), Some((0, 12)));
+    assert_eq!(insensitive.find("Hello world1"), Some((0, 12)));
+}
+
+#[test]
+fn builder_multiline() {
+    let pattern = RegexBuilder::new()
+        .multiline(true)
+        .build("^b$")
+        .expect("the pattern is valid");
+    assert_eq!(pattern.find("a\nb\nc"), Some((2, 3)));
+    assert_eq!(expression("^b$").find("a\nb\nc"), None);
+}
+
+#[test]
+fn inline_flags() {
+    assert_eq!(expression("(?i)cat").find("a CAT"), Some((2, 5)));
+    assert_eq!(expression("cat").find("a CAT"), None);
+
+    let pattern = expression("a(?i:b)c");
+    assert_eq!(pattern.find("aBc"), Some((0, 3)));
+    assert_eq!(pattern.find("ABc"), None);
+    assert_eq!(pattern.find("abC"), None);
+
+    assert!(Expression::try_from("(?z)").is_err());
+
+    let pattern = RegexBuilder::new()
+        .dot_matches_newline(true)
+        .build("(?i)a.b")
+        .expect("the pattern is valid");
+    assert_eq!(pattern.find("A\nB"), Some((0, 3)));
+    assert_eq!(expression("(?i)a.b").find("A\nB"), None);
+}
+
+#[test]
+fn approximate_size_grows_with_the_pattern() {
+    let size = |pattern: &str| expression(pattern).approximate_size();
+    assert!(size("a") < size("abc"));
+    assert!(size("abc") < size("(abc|def)+"));
+    assert!(size("(abc|def)+") < size("((abc|def)+)[a-z0-9]+"));
+}
+
+#[test]
+fn find_all_matches() {
+    assert_eq!(
+        expression(r"\d+").find_all("a1b22c333"),
+        [(1, 2), (3, 5), (6, 9)]
+    );
+    assert_eq!(expression("a*").find_all("baa"), [(0, 0), (1, 3), (3, 3)]);
+}
+
+#[test]
+fn named_captures_over_a_log() {
+    let log = "2024-05-01 INFO started\n2024-05-02 WARN slow\n2024-05-03 ERROR down\n";
+    let pattern = expression(r"(?<ts>\d\d\d\d-\d\d-\d\d) (?<level>[A-Z]+)");
+    let entries: Vec<_> = pattern
+        .captures_iter(log)
+        .map(|c| (c.name("ts"), c.name("level")))
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            (Some("2024-05-01"), Some("INFO")),
+            (Some("2024-05-02"), Some("WARN")),
+            (Some("2024-05-03"), Some("ERROR")),
+        ]
+    );
+}
+
+#[test]
+fn bytes_that_are_not_utf8() {
+    let pattern = expression(r"\d+");
+    assert_eq!(pattern.find_bytes(b"\xff\xfe abc 42"), Some((7, 9)));
+    assert_eq!(pattern.find_bytes(b"\xff\xfe abc"), None);
+    // valid UTF-8 next to an invalid byte is still decoded
+    assert_eq!(
+        expression("é").find_bytes(b"caf\xc3\xa9 \xff"),
+        Some((3, 5))
+    );
+    assert_eq!(expression(r"\xff").find_bytes(b"caf\xc3\xa9 \xff"), None);
+}
+
+#[test]
+fn out_of_steps() {
+    let pattern = RegexBuilder::new()
+        .max_steps(10_000)
+        .build("(a+)+b")
+        .expect("the pattern is valid");
+    let start = std::time::Instant::now();
+    assert!(pattern.try_find(&"a".repeat(40)).is_err());
+    assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    assert_eq!(pattern.try_find("aab"), Ok(Some((0, 3))));
+}
+
+#[test]
+fn word_boundary_keeps_combining_marks() {
+    // `\w` doesn't match the accent, and no boundary splits it from the `e`
+    assert_eq!(expression(r"\w+\b").find("cafe\u{301} au"), Some((7, 9)));
+    assert_eq!(expression(r"e\b").find("cafe\u{301}"), None);
+    assert_eq!(expression(r"e\b").find("cafe"), Some((3, 4)));
+}
+
+#[test]
+fn negated_classes() {
+    assert_eq!(expression("[^0-9]").find("x"), Some((0, 1)));
+    assert_eq!(expression("[^0-9]").find("5"), None);
+    assert_eq!(expression("[^a-c]").find("d"), Some((0, 1)));
+    assert_eq!(expression("[^a-c]").find("b"), None);
+    assert_eq!(expression("[^a]").find("\n"), None);
+}
+
+#[test]
+fn iterators_step_over_empty_matches() {
+    let pattern = expression("a*");
+    let spans: Vec<_> = pattern.matches_iter("baa").take(2).collect();
+    assert_eq!(spans, [(0, 0), (1, 3)]);
+    assert_eq!(pattern.matches_iter("baa").count(), 3);
+    let spans: Vec<_> = pattern
+        .captures_iter("baa")
+        .take(2)
+        .map(|c| c.get(0))
+        .collect();
+    assert_eq!(spans, [Some((0, 0)), Some((1, 3))]);
+    let spans: Vec<_> = pattern.matches_iter("baa").collect();
+    assert_eq!(spans, [(0, 0), (1, 3), (3, 3)]);
+    assert_eq!(pattern.captures_iter("baa").count(), 3);
+}
+
+#[test]
+fn nothing_to_repeat() {
+    assert!(Expression::try_from("+abc").is_err());
+    assert!(Expression::try_from("(?").is_err());
+    assert!(Expression::try_from("a|*b").is_err());
+    let pattern = expression(r"(a)\1+");
+    assert_eq!(pattern.find("aaaa"), Some((0, 4)));
+    assert_eq!(pattern.find("a"), None);
+}
+
+#[test]
+fn display_round_trip() {
+    for pattern in [
+        r"(a|b)+\1",
+        "(?<word>[a-z]+) \\d{2,}",
+        "^cat|dog$",
+        "(?:x|)y",
+    ] {
+        let written = expression(pattern).to_string();
+        assert_eq!(written, pattern);
+        assert_eq!(
+            expression(&written).find("abb"),
+            expression(pattern).find("abb")
+        );
+    }
+}
+
+#[test]
+fn start_anchor_applies_to_its_own_alternative() {
+    let pattern = expression("^foo|bar");
+    assert_eq!(pattern.find("xbar"), Some((1, 4)));
+    assert_eq!(pattern.find("xfoo"), None);
+    assert_eq!(pattern.find("foo"), Some((0, 3)));
+    assert_eq!(expression("^foo").find("xfoo"), None);
+}
+
+#[test]
+fn literal_search_matches_the_matchers() {
+    let input = "an ERROR, then error and ERRORS";
+    let literal = expression("ERROR");
+    let general = expression("[E]RROR");
+    assert_eq!(literal.find_all(input), [(3, 8), (25, 30)]);
+    assert_eq!(literal.find_all(input), general.find_all(input));
+
+    let counted = RegexBuilder::new()
+        .max_steps(1_000)
+        .build("ERROR")
+        .expect("the pattern is valid");
+    assert_eq!(counted.find_all(input), literal.find_all(input));
+    assert_eq!(counted.try_find(input), Ok(Some((3, 8))));
+
+    let insensitive = RegexBuilder::new()
+        .ignore_case(true)
+        .build("ERROR")
+        .expect("the pattern is valid");
+    assert_eq!(insensitive.find_all(input), [(3, 8), (15, 20), (25, 30)]);
+}
+
+#[test]
+fn repetition_bounds() {
+    let inputs = ["", "x", "a", "cab", "xbbbx", "abcabcab"];
+    for pattern in ["[abc]{1,}", "[abc]{1,10}"] {
+        for input in inputs {
+            assert_eq!(
+                expression(pattern).find(input),
+                expression("[abc]+").find(input),
+                "{pattern} on {input:?}"
+            );
+        }
+    }
+    assert_eq!(
+        expression("[abc]{1,10}").find("abcabcabcabc"),
+        Some((0, 10))
+    );
+    assert_eq!(expression("[abc]{1,3}").find("abcabc"), Some((0, 3)));
+    assert_eq!(expression("a{3}").find("aaaa"), Some((0, 3)));
+    assert_eq!(expression("a{3}").find("aa"), None);
+    assert_eq!(expression("ba{,2}").find("baaa"), Some((0, 3)));
+    assert_eq!(expression("ba{,2}").find("b"), Some((0, 1)));
+    assert!(Expression::try_from("a{3,1}").is_err());
+}